@@ -2,6 +2,7 @@ use ex::fs;
 use ex::io;
 use std::fmt::Display;
 use std::path::Path;
+use std::str::FromStr;
 
 pub fn read_to_string<P>(filename: P) -> io::Result<String>
 where
@@ -21,6 +22,20 @@ macro_rules! read_lines {
 
 pub(crate) use read_lines;
 
+/// Parses every non-empty line of `content` with `parse`, failing on the first invalid one
+/// with its 1-based line number.
+pub fn parse_lines<T>(
+    content: &str,
+    mut parse: impl FnMut(&str) -> Result<T, String>,
+) -> Result<Vec<T>, String> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .map(|(i, l)| parse(l).map_err(|e| format!("line {}: {e}", i + 1)))
+        .collect()
+}
+
 pub trait StringResults {
     fn unwrap_as_string(&self) -> String;
 }
@@ -39,3 +54,55 @@ pub fn print_parts(day: impl Display, ret1: impl StringResults, ret2: impl Strin
     println!("\t> part 1: {}", ret1.unwrap_as_string());
     println!("\t> part 2: {}", ret2.unwrap_as_string());
 }
+
+/// Number of threads the machine can run at once, 1 if it cannot tell.
+pub fn num_threads() -> usize {
+    std::thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Value after `key` on the first line of `content` that starts with it, such as the `12` of
+/// `Hit Points: 12` for the key `"Hit Points:"`.
+pub fn parse_stat<T: FromStr>(content: &str, key: &str) -> Option<T> {
+    content
+        .lines()
+        .find_map(|l| l.strip_prefix(key)?.trim().parse().ok())
+}
+
+/// Plain (ASCII) PGM image of `width` x `rows.len()` pixels. Values are scaled so that `max`
+/// is white; gray levels are capped at `u16::MAX`, the most PGM allows.
+pub fn to_pgm(width: usize, rows: &[Vec<usize>], max: usize) -> String {
+    let max = max.max(1);
+    let maxval = max.min(u16::MAX as usize);
+    let mut out = format!("P2\n{width} {}\n{maxval}\n", rows.len());
+    for row in rows {
+        let line = row.iter().map(|v| (v * maxval / max).to_string());
+        out.push_str(&line.collect::<Vec<_>>().join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+/// Plain (ASCII) PBM image of `width` x `rows.len()` pixels, non-zero values in white. PBM
+/// draws 1 in black, so those are written as 0.
+pub fn to_pbm(width: usize, rows: &[Vec<usize>]) -> String {
+    let mut out = format!("P1\n{width} {}\n", rows.len());
+    for row in rows {
+        let line = row.iter().map(|&v| if v > 0 { "0" } else { "1" });
+        out.push_str(&line.collect::<Vec<_>>().join(" "));
+        out.push('\n');
+    }
+    out
+}
+
+/// Deterministic pseudo-random numbers for generating test inputs.
+#[cfg(test)]
+pub struct Lcg(pub u64);
+
+#[cfg(test)]
+impl Lcg {
+    /// Next number in `0..n`.
+    pub fn below(&mut self, n: usize) -> usize {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1);
+        (self.0 >> 33) as usize % n
+    }
+}
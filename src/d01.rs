@@ -1,5 +1,9 @@
-use crate::common::{print_parts, read_to_string};
-use ex::io;
+use crate::common::{num_threads, print_parts};
+use ex::fs;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::{ErrorKind, Read};
+use std::thread;
 
 fn iter_decode(it_chars: impl IntoIterator<Item = char>) -> impl Iterator<Item = isize> {
     it_chars.into_iter().map(|c| match c {
@@ -9,11 +13,11 @@ fn iter_decode(it_chars: impl IntoIterator<Item = char>) -> impl Iterator<Item =
     })
 }
 
-fn get_floors(content: &str) -> isize {
+pub fn get_floors(content: &str) -> isize {
     iter_decode(content.chars()).sum()
 }
 
-fn get_pos_into_basement(content: &str) -> Option<usize> {
+pub fn get_pos_into_basement(content: &str) -> Option<usize> {
     let mut floor = 0;
     iter_decode(content.chars())
         .position(|m| {
@@ -23,13 +27,197 @@ fn get_pos_into_basement(content: &str) -> Option<usize> {
         .and_then(|p| Some(p + 1))
 }
 
-fn part1() -> io::Result<isize> {
-    let content = read_to_string("data/d01.txt")?;
-    Ok(get_floors(&content))
+/// Summary of a walk through the building, built in a single pass over the instructions.
+/// Positions are 1-based character positions; position 0 is the start, on floor 0.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Trajectory {
+    pub final_floor: isize,
+    /// Lowest floor reached, with the first position it was reached at.
+    pub min: (isize, usize),
+    /// Highest floor reached, with the first position it was reached at.
+    pub max: (isize, usize),
+    /// First position at which each floor was reached.
+    pub first_reached: HashMap<isize, usize>,
+    /// Number of times each floor was entered, the start counting as one visit of floor 0.
+    pub visits: HashMap<isize, usize>,
+    /// Number of characters that are neither `(` nor `)`.
+    pub ignored: usize,
 }
-fn part2() -> io::Result<usize> {
-    let content = read_to_string("data/d01.txt")?;
-    Ok(get_pos_into_basement(&content).unwrap_or_default())
+
+impl Trajectory {
+    pub fn first_position(&self, floor: isize) -> Option<usize> {
+        self.first_reached.get(&floor).copied()
+    }
+}
+
+pub fn trajectory(content: &str) -> Trajectory {
+    let mut traj = Trajectory {
+        first_reached: HashMap::from([(0, 0)]),
+        visits: HashMap::from([(0, 1)]),
+        ..Default::default()
+    };
+    let mut floor = 0;
+    for (i, c) in content.chars().enumerate() {
+        let pos = i + 1;
+        floor += match c {
+            '(' => 1,
+            ')' => -1,
+            _ => {
+                traj.ignored += 1;
+                continue;
+            }
+        };
+        traj.first_reached.entry(floor).or_insert(pos);
+        *traj.visits.entry(floor).or_default() += 1;
+        if floor < traj.min.0 {
+            traj.min = (floor, pos);
+        }
+        if floor > traj.max.0 {
+            traj.max = (floor, pos);
+        }
+    }
+    traj.final_floor = floor;
+    traj
+}
+
+const OPEN_WORD: u64 = u64::from_ne_bytes([b'('; 8]);
+const CLOSE_WORD: u64 = u64::from_ne_bytes([b')'; 8]);
+const LOW_7_BITS: u64 = u64::from_ne_bytes([0x7f; 8]);
+
+/// Number of bytes of `word` equal to the byte repeated in `pattern`.
+fn count_matching_bytes(word: u64, pattern: u64) -> u32 {
+    let v = word ^ pattern;
+    // The high bit of each byte ends up set iff that byte of `v` is zero, without carries
+    // spilling over into the next byte.
+    (!(((v & LOW_7_BITS) + LOW_7_BITS) | v | LOW_7_BITS)).count_ones()
+}
+
+fn byte_delta(b: u8) -> isize {
+    match b {
+        b'(' => 1,
+        b')' => -1,
+        _ => 0,
+    }
+}
+
+/// Net floor change of `bytes`, scanning 8 bytes at a time.
+/// UTF-8 continuation bytes never look like parentheses, so this works on any text.
+pub fn count_floor_bytes(bytes: &[u8]) -> isize {
+    let mut words = bytes.chunks_exact(8);
+    let mut delta = 0;
+    for word in &mut words {
+        let word = u64::from_ne_bytes(word.try_into().unwrap());
+        delta += count_matching_bytes(word, OPEN_WORD) as isize;
+        delta -= count_matching_bytes(word, CLOSE_WORD) as isize;
+    }
+    delta
+        + words
+            .remainder()
+            .iter()
+            .map(|b| byte_delta(*b))
+            .sum::<isize>()
+}
+
+/// Final floor for instructions read from `reader` in chunks of `chunk_size` bytes.
+pub fn floor_from_reader(mut reader: impl Read, chunk_size: usize) -> std::io::Result<isize> {
+    let mut buffer = vec![0; chunk_size.max(1)];
+    let mut floor = 0;
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(floor),
+            Ok(n) => floor += count_floor_bytes(&buffer[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Length of the chunks `len` bytes are split into for `threads` threads, never using more
+/// threads than the machine can run at once.
+fn chunk_len(len: usize, threads: usize) -> usize {
+    len.div_ceil(threads.clamp(1, num_threads())).max(1)
+}
+
+/// Final floor, with `bytes` split evenly across `threads` threads (at most one per core).
+pub fn floor_parallel(bytes: &[u8], threads: usize) -> isize {
+    thread::scope(|s| {
+        let handles: Vec<_> = bytes
+            .chunks(chunk_len(bytes.len(), threads))
+            .map(|chunk| s.spawn(move || count_floor_bytes(chunk)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum()
+    })
+}
+
+/// Net floor change over a chunk, lowest floor reached relative to the start of the chunk,
+/// and number of characters in it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct ChunkSummary {
+    delta: isize,
+    min_prefix: isize,
+    chars: usize,
+}
+
+/// Whether `b` starts a UTF-8 character, i.e. is not a continuation byte.
+fn is_char_start(b: u8) -> bool {
+    (b as i8) >= -0x40
+}
+
+fn summarize(bytes: &[u8]) -> ChunkSummary {
+    let mut summary = ChunkSummary::default();
+    for b in bytes {
+        summary.delta += byte_delta(*b);
+        summary.min_prefix = summary.min_prefix.min(summary.delta);
+        summary.chars += is_char_start(*b) as usize;
+    }
+    summary
+}
+
+/// 1-based character position at which the basement (floor -1) is first entered, like
+/// [`get_pos_into_basement`] but on the UTF-8 bytes of the instructions.
+///
+/// Chunks are summarized in parallel; the running floor before each chunk plus its
+/// prefix minimum then tells which chunk holds the crossing, and only that one is rescanned.
+pub fn basement_position_parallel(bytes: &[u8], threads: usize) -> Option<usize> {
+    let chunk_len = chunk_len(bytes.len(), threads);
+    let summaries: Vec<ChunkSummary> = thread::scope(|s| {
+        let handles: Vec<_> = bytes
+            .chunks(chunk_len)
+            .map(|chunk| s.spawn(move || summarize(chunk)))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let mut floor = 0;
+    let mut chars = 0;
+    for (chunk, summary) in bytes.chunks(chunk_len).zip(summaries) {
+        if floor + summary.min_prefix <= -1 {
+            let offset = chunk.iter().position(|b| {
+                floor += byte_delta(*b);
+                floor == -1
+            })?;
+            let before = chunk[..offset]
+                .iter()
+                .filter(|b| is_char_start(**b))
+                .count();
+            return Some(chars + before + 1);
+        }
+        floor += summary.delta;
+        chars += summary.chars;
+    }
+    None
+}
+
+const CHUNK_SIZE: usize = 1 << 16;
+
+fn part1() -> Result<isize, Box<dyn Error>> {
+    Ok(floor_from_reader(
+        fs::File::open("data/d01.txt")?,
+        CHUNK_SIZE,
+    )?)
+}
+fn part2() -> Result<usize, Box<dyn Error>> {
+    let bytes = fs::read("data/d01.txt")?;
+    Ok(basement_position_parallel(&bytes, num_threads()).unwrap_or_default())
 }
 
 pub fn main() {
@@ -39,6 +227,7 @@ pub fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::Lcg;
 
     #[test]
     fn test_get_floors() {
@@ -56,4 +245,127 @@ mod tests {
         assert_eq!(get_pos_into_basement("(()))"), Some(5));
         assert_eq!(get_pos_into_basement("(()))())()(()())()()()"), Some(5));
     }
+
+    /// Deterministic pseudo-random instructions, with some noise bytes.
+    fn generate(len: usize, seed: u64) -> Vec<u8> {
+        let mut rng = Lcg(seed);
+        (0..len)
+            .map(|_| match rng.below(8) {
+                0..=2 => b'(',
+                3..=6 => b')',
+                _ => b'x',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_count_floor_bytes() {
+        assert_eq!(count_floor_bytes(b""), 0);
+        assert_eq!(count_floor_bytes(b"(a) )f( ("), 1);
+        assert_eq!(count_floor_bytes("((((((((é))".as_bytes()), 6);
+        for len in [7, 8, 9, 1000, 1001] {
+            let bytes = generate(len, len as u64);
+            let content = String::from_utf8(bytes.clone()).unwrap();
+            assert_eq!(count_floor_bytes(&bytes), get_floors(&content));
+        }
+    }
+
+    #[test]
+    fn test_floor_from_reader() {
+        let bytes = generate(10_000, 1);
+        let expected = count_floor_bytes(&bytes);
+        for chunk_size in [1, 7, 64, 4096, 1 << 20] {
+            assert_eq!(
+                floor_from_reader(std::io::Cursor::new(&bytes), chunk_size).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_floor_parallel() {
+        let bytes = generate(10_001, 2);
+        for threads in [0, 1, 3, 8, 20_000] {
+            assert_eq!(floor_parallel(&bytes, threads), count_floor_bytes(&bytes));
+        }
+        assert_eq!(floor_parallel(b"", 4), 0);
+    }
+
+    #[test]
+    fn test_basement_position_parallel() {
+        for (content, threads) in [
+            ("", 4),
+            ("(", 2),
+            ("(()))", 3),
+            ("((((((()))))))))", 4),
+            ("(é)ü))", 2),
+        ] {
+            assert_eq!(
+                basement_position_parallel(content.as_bytes(), threads),
+                get_pos_into_basement(content)
+            );
+        }
+        for seed in 0..20 {
+            let bytes = generate(5_000, seed);
+            let content = String::from_utf8(bytes.clone()).unwrap();
+            for threads in [1, 2, 7, 16] {
+                assert_eq!(
+                    basement_position_parallel(&bytes, threads),
+                    get_pos_into_basement(&content)
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "slow"]
+    fn test_huge_input() {
+        let mut bytes = generate(1 << 28, 3);
+        // Keep the walk above ground until the very end
+        bytes
+            .iter_mut()
+            .for_each(|b| *b = if *b == b')' { b'(' } else { *b });
+        bytes.extend(std::iter::repeat_n(b')', 1 << 29));
+        let floor = floor_parallel(&bytes, 8);
+        assert_eq!(
+            floor_from_reader(std::io::Cursor::new(&bytes), CHUNK_SIZE).unwrap(),
+            floor
+        );
+        let up = count_floor_bytes(&bytes[..1 << 28]) as usize;
+        assert_eq!(
+            basement_position_parallel(&bytes, 8),
+            Some((1 << 28) + up + 1)
+        );
+    }
+
+    #[test]
+    fn test_trajectory() {
+        let traj = trajectory("(()a))())x");
+        assert_eq!(traj.final_floor, -2);
+        assert_eq!(traj.min, (-2, 9));
+        assert_eq!(traj.max, (2, 2));
+        assert_eq!(traj.first_position(1), Some(1));
+        assert_eq!(traj.first_position(-1), Some(6));
+        assert_eq!(traj.first_position(3), None);
+        assert_eq!(
+            traj.visits,
+            HashMap::from([(-2, 1), (-1, 2), (0, 3), (1, 2), (2, 1)])
+        );
+        assert_eq!(traj.ignored, 2);
+
+        let empty = trajectory("");
+        assert_eq!(empty.final_floor, 0);
+        assert_eq!(empty.min, (0, 0));
+        assert_eq!(empty.max, (0, 0));
+        assert_eq!(empty.first_position(0), Some(0));
+    }
+
+    #[test]
+    fn test_trajectory_matches_parts() {
+        for content in ["", "(", "(()))", "())((", "(a) )f( ("] {
+            let traj = trajectory(content);
+            assert_eq!(traj.final_floor, get_floors(content));
+            assert_eq!(traj.first_position(-1), get_pos_into_basement(content));
+        }
+    }
 }
@@ -1,43 +1,334 @@
-use crate::common::{print_parts, read_lines};
-use ex::io;
-use std::io::BufRead;
+use crate::common::{parse_lines, print_parts, read_to_string};
+use std::error::Error;
+use std::fmt::{self, Display};
 
-fn get_wrapping_paper(dims: &[u32; 3]) -> u32 {
-    let mut areas = [dims[0] * dims[1], dims[0] * dims[2], dims[1] * dims[2]];
-    areas.sort();
-    2 * (areas[0] + areas[1] + areas[2]) + areas[0]
+fn surface(dims: &[u32; 3]) -> Option<u32> {
+    let [l, w, h] = *dims;
+    l.checked_mul(w)?
+        .checked_add(l.checked_mul(h)?)?
+        .checked_add(w.checked_mul(h)?)?
+        .checked_mul(2)
 }
 
-fn get_ribbon(dims: &[u32; 3]) -> u32 {
-    let mut sorted = dims.clone();
+fn volume(dims: &[u32; 3]) -> Option<u32> {
+    dims.iter().try_fold(1u32, |acc, d| acc.checked_mul(*d))
+}
+
+fn sorted(dims: &[u32; 3]) -> [u32; 3] {
+    let mut sorted = *dims;
     sorted.sort();
-    2 * (sorted[0] + sorted[1]) + sorted[0] * sorted[1] * sorted[2]
+    sorted
+}
+
+fn smallest_side(dims: &[u32; 3]) -> Option<u32> {
+    let [a, b, _] = sorted(dims);
+    a.checked_mul(b)
 }
 
-fn parse_dims(s: &str) -> Option<[u32; 3]> {
-    let dims: Vec<u32> = s
-        .split("x")
-        .map(|x| x.parse::<u32>())
-        .collect::<Result<Vec<u32>, _>>()
-        .ok()?;
-    match dims.len() {
-        3 => Some([dims[0], dims[1], dims[2]]),
-        _ => None,
+fn smallest_perimeter(dims: &[u32; 3]) -> Option<u32> {
+    let [a, b, _] = sorted(dims);
+    a.checked_add(b)?.checked_mul(2)
+}
+
+/// How much paper and ribbon a present needs, `None` on overflow.
+pub trait WrappingRules {
+    /// Extra paper on top of the surface area.
+    fn slack(&self, dims: &[u32; 3]) -> Option<u32>;
+    /// Ribbon wrapped around the present, bow excluded.
+    fn wrap(&self, dims: &[u32; 3]) -> Option<u32>;
+    fn bow(&self, dims: &[u32; 3]) -> Option<u32>;
+
+    fn paper(&self, dims: &[u32; 3]) -> Option<u32> {
+        surface(dims)?.checked_add(self.slack(dims)?)
+    }
+
+    fn ribbon(&self, dims: &[u32; 3]) -> Option<u32> {
+        self.wrap(dims)?.checked_add(self.bow(dims)?)
     }
 }
 
-pub fn part1() -> io::Result<u32> {
-    Ok(read_lines!("data/d02.txt")?
-        .filter_map(|l| parse_dims(&l))
-        .map(|dims| get_wrapping_paper(&dims))
-        .sum())
+/// The rules from the puzzle: the area of the smallest side as slack, the smallest perimeter
+/// as wrap and the volume as bow.
+pub struct ElvesRules;
+
+impl WrappingRules for ElvesRules {
+    fn slack(&self, dims: &[u32; 3]) -> Option<u32> {
+        smallest_side(dims)
+    }
+
+    fn wrap(&self, dims: &[u32; 3]) -> Option<u32> {
+        smallest_perimeter(dims)
+    }
+
+    fn bow(&self, dims: &[u32; 3]) -> Option<u32> {
+        volume(dims)
+    }
+}
+
+/// Slack as a percentage of the surface area and a bow of fixed length.
+pub struct FlatRateRules {
+    pub slack_percent: u32,
+    pub bow_length: u32,
+}
+
+impl WrappingRules for FlatRateRules {
+    fn slack(&self, dims: &[u32; 3]) -> Option<u32> {
+        Some(surface(dims)?.checked_mul(self.slack_percent)? / 100)
+    }
+
+    fn wrap(&self, dims: &[u32; 3]) -> Option<u32> {
+        smallest_perimeter(dims)
+    }
+
+    fn bow(&self, _dims: &[u32; 3]) -> Option<u32> {
+        Some(self.bow_length)
+    }
 }
 
-pub fn part2() -> io::Result<u32> {
-    Ok(read_lines!("data/d02.txt")?
-        .filter_map(|l| parse_dims(&l))
-        .map(|dims| get_ribbon(&dims))
-        .sum())
+fn get_wrapping_paper(dims: &[u32; 3]) -> Option<u32> {
+    ElvesRules.paper(dims)
+}
+
+fn get_ribbon(dims: &[u32; 3]) -> Option<u32> {
+    ElvesRules.ribbon(dims)
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct PresentReport {
+    pub dims: [u32; 3],
+    pub surface: u32,
+    /// Area of the smallest side.
+    pub smallest_side: u32,
+    pub volume: u32,
+    pub paper: u32,
+    /// Ribbon wrapped around the present, bow excluded.
+    pub ribbon: u32,
+    pub bow: u32,
+}
+
+impl PresentReport {
+    pub fn new(dims: [u32; 3], rules: &impl WrappingRules) -> Option<Self> {
+        Some(Self {
+            dims,
+            surface: surface(&dims)?,
+            smallest_side: smallest_side(&dims)?,
+            volume: volume(&dims)?,
+            paper: rules.paper(&dims)?,
+            ribbon: rules.wrap(&dims)?,
+            bow: rules.bow(&dims)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct OrderReport {
+    pub presents: Vec<PresentReport>,
+    pub total_paper: u32,
+    /// Total ribbon, bows included.
+    pub total_ribbon: u32,
+}
+
+impl OrderReport {
+    pub fn new(
+        dims: impl IntoIterator<Item = [u32; 3]>,
+        rules: &impl WrappingRules,
+    ) -> Result<Self, String> {
+        let mut report = Self {
+            presents: vec![],
+            total_paper: 0,
+            total_ribbon: 0,
+        };
+        for (i, d) in dims.into_iter().enumerate() {
+            let overflow = || format!("present {}: overflow", i + 1);
+            let present = PresentReport::new(d, rules).ok_or_else(overflow)?;
+            report.total_paper = report
+                .total_paper
+                .checked_add(present.paper)
+                .ok_or_else(overflow)?;
+            report.total_ribbon = (present.ribbon.checked_add(present.bow))
+                .and_then(|r| report.total_ribbon.checked_add(r))
+                .ok_or_else(overflow)?;
+            report.presents.push(present);
+        }
+        Ok(report)
+    }
+
+    /// Present with the largest volume, the first one on ties.
+    pub fn largest(&self) -> Option<&PresentReport> {
+        self.presents.iter().rev().max_by_key(|p| p.volume)
+    }
+
+    /// Present with the smallest volume, the first one on ties.
+    pub fn smallest(&self) -> Option<&PresentReport> {
+        self.presents.iter().min_by_key(|p| p.volume)
+    }
+}
+
+impl Display for OrderReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>12} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            "dims", "surface", "smallest", "paper", "ribbon", "bow"
+        )?;
+        for p in &self.presents {
+            let dims = format!("{}x{}x{}", p.dims[0], p.dims[1], p.dims[2]);
+            writeln!(
+                f,
+                "{:>12} {:>8} {:>8} {:>8} {:>8} {:>8}",
+                dims, p.surface, p.smallest_side, p.paper, p.ribbon, p.bow
+            )?;
+        }
+        writeln!(f, "total paper: {}", self.total_paper)?;
+        writeln!(f, "total ribbon: {}", self.total_ribbon)?;
+        if let (Some(l), Some(s)) = (self.largest(), self.smallest()) {
+            writeln!(f, "largest: {:?} ({})", l.dims, l.volume)?;
+            writeln!(f, "smallest: {:?} ({})", s.dims, s.volume)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unit {
+    Cm,
+    In,
+}
+
+/// Parsed dimensions in fixed point: each value counts units of `10^-precision`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dims {
+    pub values: [u32; 3],
+    pub precision: u32,
+    pub unit: Option<Unit>,
+}
+
+impl Dims {
+    /// Values converted to `unit`, rounded to the nearest step of `10^-precision`. Dimensions
+    /// without a unit are taken to be in `unit` already. `None` on overflow.
+    pub fn in_unit(&self, unit: Unit) -> Option<[u32; 3]> {
+        // Hundredths of a centimeter per inch
+        const CM_PER_IN: u64 = 254;
+        let convert = |v: u32| -> Option<u32> {
+            let v = v as u64;
+            let converted = match (self.unit, unit) {
+                (None, _) | (Some(Unit::Cm), Unit::Cm) | (Some(Unit::In), Unit::In) => v,
+                (Some(Unit::In), Unit::Cm) => (v * CM_PER_IN + 50) / 100,
+                (Some(Unit::Cm), Unit::In) => (v * 100 + CM_PER_IN / 2) / CM_PER_IN,
+            };
+            u32::try_from(converted).ok()
+        };
+        let [l, w, h] = self.values;
+        Some([convert(l)?, convert(w)?, convert(h)?])
+    }
+}
+
+/// Parses a decimal with at most `precision` decimal places, scaled by `10^precision`,
+/// with an optional unit suffix.
+fn parse_value(s: &str, precision: u32) -> Result<(u32, Option<Unit>), String> {
+    let s = s.trim();
+    let (number, unit) = if let Some(number) = s.strip_suffix("cm") {
+        (number.trim_end(), Some(Unit::Cm))
+    } else if let Some(number) = s.strip_suffix("in") {
+        (number.trim_end(), Some(Unit::In))
+    } else {
+        (s, None)
+    };
+    let (int, frac) = match number.split_once('.') {
+        Some((_, "")) => return Err(format!("invalid value '{s}'")),
+        Some((int, frac)) => (int, frac),
+        None => (number, ""),
+    };
+    if int.is_empty() || !int.bytes().chain(frac.bytes()).all(|b| b.is_ascii_digit()) {
+        return Err(format!("invalid value '{s}'"));
+    }
+    if frac.len() > precision as usize {
+        return Err(format!("'{s}' has more than {precision} decimal place(s)"));
+    }
+    let digits = int.bytes().chain(frac.bytes());
+    let padding = precision as usize - frac.len();
+    let value = digits
+        .chain(std::iter::repeat_n(b'0', padding))
+        .try_fold(0u32, |acc, b| {
+            acc.checked_mul(10)?.checked_add((b - b'0') as u32)
+        })
+        .ok_or(format!("'{s}' is too large"))?;
+    Ok((value, unit))
+}
+
+/// Parses `LxWxH`, allowing whitespace around values, up to `precision` decimal places and
+/// a `cm` or `in` suffix on any value. A suffix applies to the whole line; mixing units is an
+/// error.
+pub fn parse_dims_with(s: &str, precision: u32) -> Result<Dims, String> {
+    let values = s
+        .split('x')
+        .map(|v| parse_value(v, precision))
+        .collect::<Result<Vec<_>, _>>()?;
+    let [(l, u1), (w, u2), (h, u3)] = values[..] else {
+        return Err(format!("expected 3 dimensions, found {}", values.len()));
+    };
+    let mut units = [u1, u2, u3].into_iter().flatten();
+    let unit = units.next();
+    if units.any(|u| Some(u) != unit) {
+        return Err("mixed units".to_owned());
+    }
+    Ok(Dims {
+        values: [l, w, h],
+        precision,
+        unit,
+    })
+}
+
+/// Parses whole numbers without a unit, as in the puzzle input.
+fn parse_dims(s: &str) -> Result<[u32; 3], String> {
+    let dims = parse_dims_with(s, 0)?;
+    match dims.unit {
+        Some(_) => Err("unexpected unit".to_owned()),
+        None => Ok(dims.values),
+    }
+}
+
+/// Parses every non-empty line of `content` with up to `precision` decimal places, and
+/// converts it to `unit`. The results count steps of `10^-precision` `unit`, so rules see
+/// lengths in those steps, areas in their squares and volumes in their cubes.
+pub fn parse_order(content: &str, precision: u32, unit: Unit) -> Result<Vec<[u32; 3]>, String> {
+    parse_lines(content, |l| {
+        parse_dims_with(l, precision).and_then(|d| d.in_unit(unit).ok_or("overflow".to_owned()))
+    })
+}
+
+/// Report on an order with decimal dimensions and units, see [`parse_order`].
+pub fn report_with(
+    content: &str,
+    precision: u32,
+    unit: Unit,
+    rules: &impl WrappingRules,
+) -> Result<OrderReport, String> {
+    OrderReport::new(parse_order(content, precision, unit)?, rules)
+}
+
+/// Parses every non-empty line, failing on the first invalid one.
+fn read_dims() -> Result<Vec<[u32; 3]>, Box<dyn Error>> {
+    Ok(parse_lines(&read_to_string("data/d02.txt")?, parse_dims)?)
+}
+
+fn total(dims: &[[u32; 3]], f: impl Fn(&[u32; 3]) -> Option<u32>) -> Result<u32, String> {
+    dims.iter().enumerate().try_fold(0u32, |acc, (i, d)| {
+        f(d).and_then(|x| acc.checked_add(x))
+            .ok_or(format!("present {}: overflow", i + 1))
+    })
+}
+
+pub fn part1() -> Result<u32, Box<dyn Error>> {
+    Ok(total(&read_dims()?, get_wrapping_paper)?)
+}
+
+pub fn part2() -> Result<u32, Box<dyn Error>> {
+    Ok(total(&read_dims()?, get_ribbon)?)
+}
+
+pub fn report(rules: &impl WrappingRules) -> Result<OrderReport, Box<dyn Error>> {
+    Ok(OrderReport::new(read_dims()?, rules)?)
 }
 
 pub fn main() {
@@ -50,17 +341,64 @@ mod tests {
 
     #[test]
     fn test_get_wrapping_paper() {
-        assert_eq!(get_wrapping_paper(&[0, 0, 0]), 0);
-        assert_eq!(get_wrapping_paper(&[0, 0, 1]), 0);
-        assert_eq!(get_wrapping_paper(&[0, 1, 1]), 2);
-        assert_eq!(get_wrapping_paper(&[1, 1, 1]), 7);
-        assert_eq!(get_wrapping_paper(&[1, 2, 1]), 11);
+        assert_eq!(get_wrapping_paper(&[0, 0, 0]), Some(0));
+        assert_eq!(get_wrapping_paper(&[0, 0, 1]), Some(0));
+        assert_eq!(get_wrapping_paper(&[0, 1, 1]), Some(2));
+        assert_eq!(get_wrapping_paper(&[1, 1, 1]), Some(7));
+        assert_eq!(get_wrapping_paper(&[1, 2, 1]), Some(11));
+        assert_eq!(get_wrapping_paper(&[1 << 16, 1 << 16, 1]), None);
     }
 
     #[test]
     fn test_get_ribbon() {
-        assert_eq!(get_ribbon(&[2, 3, 4]), 34);
-        assert_eq!(get_ribbon(&[1, 1, 10]), 14);
+        assert_eq!(get_ribbon(&[2, 3, 4]), Some(34));
+        assert_eq!(get_ribbon(&[1, 1, 10]), Some(14));
+        assert_eq!(get_ribbon(&[1 << 11, 1 << 11, 1 << 11]), None);
+    }
+
+    #[test]
+    fn test_flat_rate_rules() {
+        let rules = FlatRateRules {
+            slack_percent: 10,
+            bow_length: 3,
+        };
+        assert_eq!(rules.paper(&[2, 3, 4]), Some(57));
+        assert_eq!(rules.ribbon(&[2, 3, 4]), Some(13));
+    }
+
+    #[test]
+    fn test_present_report() {
+        assert_eq!(
+            PresentReport::new([2, 3, 4], &ElvesRules),
+            Some(PresentReport {
+                dims: [2, 3, 4],
+                surface: 52,
+                smallest_side: 6,
+                volume: 24,
+                paper: 58,
+                ribbon: 10,
+                bow: 24,
+            })
+        );
+    }
+
+    #[test]
+    fn test_order_report() {
+        let report = OrderReport::new([[2, 3, 4], [1, 1, 10], [4, 3, 2]], &ElvesRules).unwrap();
+        assert_eq!(report.presents.len(), 3);
+        assert_eq!(report.total_paper, 58 + 43 + 58);
+        assert_eq!(report.total_ribbon, 34 + 14 + 34);
+        assert_eq!(report.largest().unwrap().dims, [2, 3, 4]);
+        assert_eq!(report.smallest().unwrap().dims, [1, 1, 10]);
+
+        let empty = OrderReport::new([], &ElvesRules).unwrap();
+        assert_eq!(empty.total_paper, 0);
+        assert_eq!(empty.largest(), None);
+
+        assert_eq!(
+            OrderReport::new([[1, 1, 1], [1 << 16, 1 << 16, 1]], &ElvesRules),
+            Err("present 2: overflow".to_owned())
+        );
     }
 
     #[test]
@@ -68,5 +406,89 @@ mod tests {
         assert_eq!(parse_dims("0x0x0").unwrap(), [0, 0, 0]);
         assert_eq!(parse_dims("0x1x0").unwrap(), [0, 1, 0]);
         assert_eq!(parse_dims("123x4x789").unwrap(), [123, 4, 789]);
+        assert_eq!(parse_dims(" 1 x 2 x 3 ").unwrap(), [1, 2, 3]);
+        assert!(parse_dims("1x2").is_err());
+        assert!(parse_dims("1x2x3x4").is_err());
+        assert!(parse_dims("1x-2x3").is_err());
+        assert!(parse_dims("1x2.5x3").is_err());
+        assert!(parse_dims("1x2x99999999999").is_err());
+        assert_eq!(parse_dims("1x2x3cm"), Err("unexpected unit".to_owned()));
+    }
+
+    #[test]
+    fn test_parse_dims_with() {
+        assert_eq!(
+            parse_dims_with("1.5x2x0.25", 2),
+            Ok(Dims {
+                values: [150, 200, 25],
+                precision: 2,
+                unit: None
+            })
+        );
+        assert_eq!(
+            parse_dims_with("1 x 2 x 3cm", 0),
+            Ok(Dims {
+                values: [1, 2, 3],
+                precision: 0,
+                unit: Some(Unit::Cm)
+            })
+        );
+        assert_eq!(
+            parse_dims_with("1in x 2.5in x 3 in", 1),
+            Ok(Dims {
+                values: [10, 25, 30],
+                precision: 1,
+                unit: Some(Unit::In)
+            })
+        );
+        assert_eq!(
+            parse_dims_with("1cmx2inx3", 0),
+            Err("mixed units".to_owned())
+        );
+        assert_eq!(
+            parse_dims_with("1x2x0.125", 2),
+            Err("'0.125' has more than 2 decimal place(s)".to_owned())
+        );
+        assert_eq!(
+            parse_dims_with("1x2xft", 0),
+            Err("invalid value 'ft'".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_parse_value_suffix() {
+        assert_eq!(
+            parse_dims_with("1x2x3cmcm", 0),
+            Err("invalid value '3cmcm'".to_owned())
+        );
+        assert_eq!(
+            parse_dims_with("1x2x1.", 1),
+            Err("invalid value '1.'".to_owned())
+        );
+        assert!(parse_dims_with("1x2x.5", 1).is_err());
+    }
+
+    #[test]
+    fn test_in_unit() {
+        let dims = parse_dims_with("1inx2inx0.5in", 1).unwrap();
+        assert_eq!(dims.in_unit(Unit::Cm), Some([25, 51, 13]));
+        assert_eq!(dims.in_unit(Unit::In), Some([10, 20, 5]));
+        let dims = parse_dims_with("2.54cmx1x0cm", 2).unwrap();
+        assert_eq!(dims.in_unit(Unit::In), Some([100, 39, 0]));
+        let dims = parse_dims_with("4000000000inx1inx1in", 0).unwrap();
+        assert_eq!(dims.in_unit(Unit::Cm), None);
+    }
+
+    #[test]
+    fn test_report_with() {
+        // 2x3x4 cm in tenths: 5200 + 600 hundredths of cm² of paper
+        let report = report_with("2x3x4cm\n\n1.0inx1inx1in\n", 1, Unit::Cm, &ElvesRules).unwrap();
+        assert_eq!(report.presents[0].dims, [20, 30, 40]);
+        assert_eq!(report.presents[0].paper, 5800);
+        assert_eq!(report.presents[1].dims, [25, 25, 25]);
+        assert_eq!(
+            report_with("1x2x3\n1x2x3ft", 0, Unit::Cm, &ElvesRules).unwrap_err(),
+            "line 2: invalid value '3ft'"
+        );
     }
 }
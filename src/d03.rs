@@ -1,9 +1,11 @@
-use crate::common::{print_parts, read_to_string};
+use crate::common::{print_parts, read_to_string, to_pgm};
 use ex::io;
 
-use std::collections::HashSet;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
 
-type Coords = (isize, isize);
+pub type Coords = (isize, isize);
 
 fn decode(c: char) -> Option<Coords> {
     match c {
@@ -19,36 +21,239 @@ fn add_coords(c1: Coords, c2: Coords) -> Coords {
     return (c1.0 + c2.0, c1.1 + c2.1);
 }
 
-fn do_part1(content: &str) -> usize {
-    let mut cur: Coords = (0, 0);
-    let mut visited = HashSet::<Coords>::new();
-    visited.insert(cur);
-    content.chars().filter_map(decode).for_each(|m| {
-        cur = add_coords(cur, m);
-        visited.insert(cur);
+/// Number of presents delivered to each house.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct HeatMap(pub HashMap<Coords, usize>);
+
+const ASCII_RAMP: &[u8] = b" .:-=+*#%@";
+
+impl HeatMap {
+    /// Lowest and highest coordinates among visited houses.
+    pub fn bounding_box(&self) -> Option<(Coords, Coords)> {
+        let xs = self.0.keys().map(|c| c.0);
+        let ys = self.0.keys().map(|c| c.1);
+        Some((
+            (xs.clone().min()?, ys.clone().min()?),
+            (xs.max()?, ys.max()?),
+        ))
+    }
+
+    fn max_count(&self) -> usize {
+        self.0.values().copied().max().unwrap_or_default()
+    }
+
+    /// Counts over the bounding box, northernmost row first.
+    fn rows(&self) -> Vec<Vec<usize>> {
+        let Some(((x_min, y_min), (x_max, y_max))) = self.bounding_box() else {
+            return vec![];
+        };
+        (y_min..=y_max)
+            .rev()
+            .map(|y| {
+                (x_min..=x_max)
+                    .map(|x| self.0.get(&(x, y)).copied().unwrap_or_default())
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// One character per house, from `' '` for no present up to `'@'` for the busiest house.
+    pub fn to_ascii(&self) -> String {
+        let max = self.max_count();
+        let top = ASCII_RAMP.len() - 1;
+        self.rows()
+            .iter()
+            .map(|row| {
+                let mut line: String = row
+                    .iter()
+                    .map(|&c| match c {
+                        0 => ASCII_RAMP[0] as char,
+                        _ if max == 1 => ASCII_RAMP[top] as char,
+                        _ => ASCII_RAMP[1 + (c - 1) * (top - 1) / (max - 1)] as char,
+                    })
+                    .collect();
+                line.push('\n');
+                line
+            })
+            .collect()
+    }
+
+    /// Plain (ASCII) PGM image, one gray pixel per house.
+    pub fn to_pgm(&self) -> String {
+        let rows = self.rows();
+        to_pgm(rows.first().map_or(0, |r| r.len()), &rows, self.max_count())
+    }
+
+    /// Plain (ASCII) PPM image, unvisited houses in black and visited ones from blue (fewest
+    /// presents) to red (most presents).
+    pub fn to_ppm(&self) -> String {
+        let rows = self.rows();
+        let max = self.max_count().max(1);
+        let mut out = format!(
+            "P3\n{} {}\n255\n",
+            rows.first().map_or(0, |r| r.len()),
+            rows.len()
+        );
+        for row in rows {
+            let line = row.iter().map(|&c| match c {
+                0 => "0 0 0".to_owned(),
+                _ => {
+                    let heat = c * 255 / max;
+                    format!("{heat} 0 {}", 255 - heat)
+                }
+            });
+            out.push_str(&line.collect::<Vec<_>>().join(" "));
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// How moves are handed out to the agents. Moves are counted after dropping
+/// non-direction characters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Dispatch {
+    /// Move `i` goes to agent `i % n`.
+    RoundRobin,
+    /// Runs of that many consecutive moves go to the same agent, agents taking turns.
+    Chunks(usize),
+    /// Move `i` goes to agent `labels[i]`.
+    Labels(Vec<usize>),
+}
+
+impl Dispatch {
+    fn agent(&self, i: usize, n_agents: usize) -> Result<usize, String> {
+        match self {
+            Self::RoundRobin => Ok(i % n_agents),
+            Self::Chunks(0) => Err("chunk size must be positive".to_owned()),
+            Self::Chunks(size) => Ok((i / size) % n_agents),
+            Self::Labels(labels) => match labels.get(i) {
+                Some(&a) if a < n_agents => Ok(a),
+                Some(&a) => Err(format!("move {}: unknown agent {a}", i + 1)),
+                None => Err(format!("move {}: no agent label", i + 1)),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct AgentStats {
+    pub moves: usize,
+    /// Deliveries made, the one at the starting house included.
+    pub houses_visited: usize,
+    pub unique_houses: usize,
+    /// Unique houses that at least one other agent also delivered to.
+    pub shared_houses: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Delivery {
+    pub visited: HashSet<Coords>,
+    pub presents: HeatMap,
+    pub agents: Vec<AgentStats>,
+    /// 1-based index of the first move landing on an already visited house.
+    pub first_revisit: Option<usize>,
+}
+
+/// Runs `n_agents` agents from the origin, each of them delivering a present there first.
+pub fn deliver(content: &str, n_agents: usize, dispatch: &Dispatch) -> Result<Delivery, String> {
+    if n_agents == 0 {
+        return Err("at least one agent is needed".to_owned());
+    }
+    let mut positions = vec![(0, 0); n_agents];
+    let mut houses = vec![HashSet::from([(0, 0)]); n_agents];
+    let mut presents = HeatMap(HashMap::from([((0, 0), n_agents)]));
+    let mut stats: Vec<AgentStats> = (0..n_agents)
+        .map(|_| AgentStats {
+            houses_visited: 1,
+            ..Default::default()
+        })
+        .collect();
+    let mut first_revisit = None;
+    for (i, m) in content.chars().filter_map(decode).enumerate() {
+        let agent = dispatch.agent(i, n_agents)?;
+        positions[agent] = add_coords(positions[agent], m);
+        houses[agent].insert(positions[agent]);
+        let count = presents.0.entry(positions[agent]).or_default();
+        if *count > 0 && first_revisit.is_none() {
+            first_revisit = Some(i + 1);
+        }
+        *count += 1;
+        stats[agent].moves += 1;
+        stats[agent].houses_visited += 1;
+    }
+
+    let mut agents_per_house = HashMap::<Coords, usize>::new();
+    houses.iter().flatten().for_each(|h| {
+        *agents_per_house.entry(*h).or_default() += 1;
     });
-    visited.len()
+    for (agent_stats, agent_houses) in stats.iter_mut().zip(houses.iter()) {
+        agent_stats.unique_houses = agent_houses.len();
+        agent_stats.shared_houses = agent_houses
+            .iter()
+            .filter(|h| agents_per_house[h] > 1)
+            .count();
+    }
+    Ok(Delivery {
+        visited: agents_per_house.into_keys().collect(),
+        presents,
+        agents: stats,
+        first_revisit,
+    })
 }
 
-fn do_part2(content: &str) -> usize {
-    let mut cur1: Coords = (0, 0);
-    let mut cur2: Coords = (0, 0);
-    let mut visited = HashSet::<Coords>::new();
-    visited.insert(cur1);
-    content
-        .chars()
-        .filter_map(decode)
-        .enumerate()
-        .for_each(|(i, m)| {
-            if i % 2 == 0 {
-                cur1 = add_coords(cur1, m);
-                visited.insert(cur1);
-            } else {
-                cur2 = add_coords(cur2, m);
-                visited.insert(cur2);
-            }
+fn manhattan(c: Coords) -> usize {
+    c.0.unsigned_abs() + c.1.unsigned_abs()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Analysis {
+    /// Most visited houses with their number of presents, ties broken by coordinates.
+    pub top: Vec<(Coords, usize)>,
+    /// Number of houses per number of presents received.
+    pub histogram: BTreeMap<usize, usize>,
+    pub bounding_box: Option<(Coords, Coords)>,
+    /// House farthest from the origin with its Manhattan distance, ties broken by coordinates.
+    pub farthest: Option<(Coords, usize)>,
+    pub first_revisit: Option<usize>,
+}
+
+impl Delivery {
+    pub fn analyze(&self, top_k: usize) -> Analysis {
+        let mut houses: Vec<(Coords, usize)> =
+            self.presents.0.iter().map(|(h, c)| (*h, *c)).collect();
+        houses.sort_by_key(|(h, c)| (Reverse(*c), *h));
+        let mut histogram = BTreeMap::new();
+        houses.iter().for_each(|(_, c)| {
+            *histogram.entry(*c).or_default() += 1;
         });
-    visited.len()
+        let farthest = houses
+            .iter()
+            .map(|(h, _)| (*h, manhattan(*h)))
+            .max_by_key(|(h, d)| (*d, Reverse(*h)));
+        houses.truncate(top_k);
+        Analysis {
+            top: houses,
+            histogram,
+            bounding_box: self.presents.bounding_box(),
+            farthest,
+            first_revisit: self.first_revisit,
+        }
+    }
+}
+
+fn do_part1(content: &str) -> usize {
+    deliver(content, 1, &Dispatch::RoundRobin)
+        .expect("Round robin dispatch to one agent cannot fail")
+        .visited
+        .len()
+}
+
+fn do_part2(content: &str) -> usize {
+    deliver(content, 2, &Dispatch::RoundRobin)
+        .expect("Round robin dispatch to two agents cannot fail")
+        .visited
+        .len()
 }
 
 pub fn part1() -> io::Result<usize> {
@@ -59,6 +264,16 @@ pub fn part2() -> io::Result<usize> {
     Ok(do_part2(&read_to_string("data/d03.txt")?))
 }
 
+pub fn heat_map(n_agents: usize) -> Result<HeatMap, Box<dyn Error>> {
+    let content = read_to_string("data/d03.txt")?;
+    Ok(deliver(&content, n_agents, &Dispatch::RoundRobin)?.presents)
+}
+
+pub fn analysis(n_agents: usize, top_k: usize) -> Result<Analysis, Box<dyn Error>> {
+    let content = read_to_string("data/d03.txt")?;
+    Ok(deliver(&content, n_agents, &Dispatch::RoundRobin)?.analyze(top_k))
+}
+
 pub fn main() {
     print_parts(3, part1(), part2());
 }
@@ -79,4 +294,103 @@ mod tests {
         assert_eq!(do_part2("^>v<"), 3);
         assert_eq!(do_part2("^v^v^v^v^v"), 11);
     }
+
+    #[test]
+    fn test_deliver_chunks() {
+        let delivery = deliver("^^vv>>", 2, &Dispatch::Chunks(2)).unwrap();
+        // Agent 0 goes up twice then right twice, agent 1 down twice
+        assert_eq!(delivery.visited.len(), 7);
+        assert_eq!(
+            delivery.agents[0],
+            AgentStats {
+                moves: 4,
+                houses_visited: 5,
+                unique_houses: 5,
+                shared_houses: 1,
+            }
+        );
+        assert_eq!(
+            delivery.agents[1],
+            AgentStats {
+                moves: 2,
+                houses_visited: 3,
+                unique_houses: 3,
+                shared_houses: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_deliver_labels() {
+        let labels = Dispatch::Labels(vec![0, 0, 2, 2, 1]);
+        let delivery = deliver("^v^v>", 3, &labels).unwrap();
+        assert_eq!(delivery.visited.len(), 3);
+        let unique = delivery
+            .agents
+            .iter()
+            .map(|a| a.unique_houses)
+            .collect::<Vec<_>>();
+        let shared = delivery
+            .agents
+            .iter()
+            .map(|a| a.shared_houses)
+            .collect::<Vec<_>>();
+        assert_eq!(unique, [2, 2, 2]);
+        assert_eq!(shared, [2, 1, 2]);
+        assert_eq!(delivery.agents[0].houses_visited, 3);
+    }
+
+    #[test]
+    fn test_heat_map() {
+        let delivery = deliver("^>v<^>", 2, &Dispatch::RoundRobin).unwrap();
+        let heat = &delivery.presents;
+        assert_eq!(heat.0.values().sum::<usize>(), 8);
+        assert_eq!(heat.bounding_box(), Some(((0, 0), (1, 1))));
+        assert_eq!(heat.to_ascii(), "- \n@-\n");
+        assert_eq!(heat.to_pgm(), "P2\n2 2\n4\n2 0\n4 2\n");
+        assert_eq!(
+            heat.to_ppm(),
+            "P3\n2 2\n255\n127 0 128 0 0 0\n255 0 0 127 0 128\n"
+        );
+
+        let empty = HeatMap::default();
+        assert_eq!(empty.bounding_box(), None);
+        assert_eq!(empty.to_ascii(), "");
+        assert_eq!(empty.to_pgm(), "P2\n0 0\n1\n");
+    }
+
+    #[test]
+    fn test_analyze() {
+        let delivery = deliver("^^>vv<<<", 1, &Dispatch::RoundRobin).unwrap();
+        let analysis = delivery.analyze(2);
+        assert_eq!(analysis.top, [((0, 0), 2), ((-2, 0), 1)]);
+        assert_eq!(analysis.histogram, BTreeMap::from([(1, 7), (2, 1)]));
+        assert_eq!(analysis.bounding_box, Some(((-2, 0), (1, 2))));
+        assert_eq!(analysis.farthest, Some(((1, 2), 3)));
+        assert_eq!(analysis.first_revisit, Some(6));
+
+        let analysis = deliver(">>", 2, &Dispatch::RoundRobin).unwrap().analyze(10);
+        assert_eq!(analysis.top, [((0, 0), 2), ((1, 0), 2)]);
+        assert_eq!(analysis.first_revisit, Some(2));
+        assert_eq!(
+            deliver("^>", 1, &Dispatch::RoundRobin)
+                .unwrap()
+                .first_revisit,
+            None
+        );
+    }
+
+    #[test]
+    fn test_deliver_errors() {
+        assert!(deliver("^", 0, &Dispatch::RoundRobin).is_err());
+        assert!(deliver("^", 1, &Dispatch::Chunks(0)).is_err());
+        assert_eq!(
+            deliver("^v", 2, &Dispatch::Labels(vec![1])).unwrap_err(),
+            "move 2: no agent label"
+        );
+        assert_eq!(
+            deliver("^v", 2, &Dispatch::Labels(vec![1, 2])).unwrap_err(),
+            "move 2: unknown agent 2"
+        );
+    }
 }
@@ -1,12 +1,17 @@
-use crate::common::print_parts;
-use ex::io;
+use crate::common::{num_threads, print_parts, read_to_string};
 use md5;
+use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
+#[cfg(test)]
 fn hash(s: &[u8], n: usize) -> md5::Digest {
     let data = [s, &n.to_string().as_ref()].concat();
     md5::compute(data.as_slice())
 }
 
+/// Compares only the leading `b.len()` bytes of `a`, so callers can pass the whole digest.
+#[cfg(test)]
 fn le(a: &[u8], b: &[u8]) -> bool {
     match a.iter().zip(b.iter()).find(|(ai, bi)| ai != bi) {
         Some((ai, bi)) => ai < bi,
@@ -14,6 +19,7 @@ fn le(a: &[u8], b: &[u8]) -> bool {
     }
 }
 
+#[cfg(test)]
 fn simple_mine(secret: &str, max: &[u8]) -> usize {
     let mut n: usize = 0;
     let secret_bytes = secret.as_ref();
@@ -26,14 +32,400 @@ fn simple_mine(secret: &str, max: &[u8]) -> usize {
     n
 }
 
-pub fn part1() -> io::Result<usize> {
-    let secret = "ckczppom";
-    Ok(simple_mine(&secret, &[0, 0, 0x10]))
+/// What a digest must look like for a nonce to be mined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Target {
+    /// The hex digest starts with these nibbles.
+    HexPrefix(Vec<u8>),
+    /// The digest starts with at least this many zero bits.
+    LeadingZeroBits(u32),
+}
+
+impl Target {
+    /// Parses either a hex prefix such as `"00000"` or `"0000ab"`, or a bit count such as
+    /// `"20 bits"`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        if let Some(bits) = s.strip_suffix("bits") {
+            return bits
+                .trim()
+                .parse()
+                .map(Self::LeadingZeroBits)
+                .map_err(|_| format!("invalid bit count in '{s}'"));
+        }
+        s.chars()
+            .map(|c| c.to_digit(16).map(|d| d as u8))
+            .collect::<Option<Vec<u8>>>()
+            .filter(|nibbles| !nibbles.is_empty())
+            .map(Self::HexPrefix)
+            .ok_or(format!("invalid hex prefix '{s}'"))
+    }
+
+    /// Number of leading digest bits the target constrains.
+    fn bits(&self) -> usize {
+        match self {
+            Self::HexPrefix(nibbles) => 4 * nibbles.len(),
+            Self::LeadingZeroBits(bits) => *bits as usize,
+        }
+    }
+
+    /// Rejects targets longer than a digest of `len` bytes, which no nonce could ever hit.
+    pub fn check(&self, len: usize) -> Result<(), String> {
+        if self.bits() > 8 * len {
+            return Err(format!(
+                "target needs {} bits but the digest only has {}",
+                self.bits(),
+                8 * len
+            ));
+        }
+        Ok(())
+    }
+
+    fn matches(&self, digest: &[u8]) -> bool {
+        match self {
+            Self::HexPrefix(nibbles) => nibbles.iter().enumerate().all(|(i, n)| {
+                digest
+                    .get(i / 2)
+                    .is_some_and(|b| *n == if i % 2 == 0 { b >> 4 } else { b & 0xf })
+            }),
+            Self::LeadingZeroBits(bits) => {
+                let mut remaining = *bits;
+                for b in digest {
+                    if remaining <= 8 {
+                        return b.leading_zeros() >= remaining;
+                    }
+                    if *b != 0 {
+                        return false;
+                    }
+                    remaining -= 8;
+                }
+                remaining == 0
+            }
+        }
+    }
+}
+
+/// Streaming digest algorithm the miner can run.
+pub trait Hasher: Clone + Sync {
+    type Output: AsRef<[u8]>;
+    /// Length of `Output` in bytes.
+    const OUTPUT_LEN: usize;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Self::Output;
+}
+
+#[derive(Clone)]
+pub struct Md5(md5::Context);
+
+impl Hasher for Md5 {
+    type Output = [u8; 16];
+    const OUTPUT_LEN: usize = 16;
+
+    fn new() -> Self {
+        Self(md5::Context::new())
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        self.0.consume(data);
+    }
+
+    fn finalize(self) -> Self::Output {
+        self.0.compute().0
+    }
+}
+
+/// Buffering and padding shared by SHA-1 and SHA-256, which both work on 64-byte blocks and
+/// end with the message length in bits, big-endian.
+#[derive(Clone)]
+struct BlockBuffer {
+    block: [u8; 64],
+    len: usize,
+    total: u64,
+}
+
+impl BlockBuffer {
+    fn new() -> Self {
+        Self {
+            block: [0; 64],
+            len: 0,
+            total: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; 64])) {
+        self.total += data.len() as u64;
+        while !data.is_empty() {
+            let n = (64 - self.len).min(data.len());
+            self.block[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+            if self.len == 64 {
+                compress(&self.block);
+                self.len = 0;
+            }
+        }
+    }
+
+    fn finish(mut self, mut compress: impl FnMut(&[u8; 64])) {
+        let bit_len = self.total.wrapping_mul(8);
+        self.block[self.len] = 0x80;
+        self.block[self.len + 1..].fill(0);
+        if self.len >= 56 {
+            compress(&self.block);
+            self.block.fill(0);
+        }
+        self.block[56..].copy_from_slice(&bit_len.to_be_bytes());
+        compress(&self.block);
+    }
+}
+
+fn be_words<const N: usize>(block: &[u8; 64]) -> [u32; N] {
+    let mut w = [0; N];
+    for (i, chunk) in block.chunks_exact(4).enumerate() {
+        w[i] = u32::from_be_bytes(chunk.try_into().unwrap());
+    }
+    w
+}
+
+fn be_bytes<const N: usize, const M: usize>(state: &[u32; N]) -> [u8; M] {
+    let mut out = [0; M];
+    for (chunk, word) in out.chunks_exact_mut(4).zip(state) {
+        chunk.copy_from_slice(&word.to_be_bytes());
+    }
+    out
+}
+
+#[derive(Clone)]
+pub struct Sha1 {
+    state: [u32; 5],
+    buffer: BlockBuffer,
+}
+
+fn sha1_compress(state: &mut [u32; 5], block: &[u8; 64]) {
+    let mut w: [u32; 80] = be_words(block);
+    for i in 16..80 {
+        w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+    for (i, wi) in w.iter().enumerate() {
+        let (f, k) = match i {
+            0..=19 => ((b & c) | (!b & d), 0x5a827999),
+            20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+            _ => (b ^ c ^ d, 0xca62c1d6),
+        };
+        let t = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(*wi);
+        (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+impl Hasher for Sha1 {
+    type Output = [u8; 20];
+    const OUTPUT_LEN: usize = 20;
+
+    fn new() -> Self {
+        Self {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0],
+            buffer: BlockBuffer::new(),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.buffer
+            .update(data, |block| sha1_compress(state, block));
+    }
+
+    fn finalize(mut self) -> Self::Output {
+        let state = &mut self.state;
+        self.buffer.finish(|block| sha1_compress(state, block));
+        be_bytes(&self.state)
+    }
+}
+
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: BlockBuffer,
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+fn sha256_compress(state: &mut [u32; 8], block: &[u8; 64]) {
+    let mut w: [u32; 64] = be_words(block);
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+    for (k, wi) in SHA256_K.iter().zip(w) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = h
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(*k)
+            .wrapping_add(wi);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        (h, g, f, e, d, c, b, a) = (g, f, e, d.wrapping_add(t1), c, b, a, t1.wrapping_add(t2));
+    }
+    for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+        *s = s.wrapping_add(v);
+    }
+}
+
+impl Hasher for Sha256 {
+    type Output = [u8; 32];
+    const OUTPUT_LEN: usize = 32;
+
+    fn new() -> Self {
+        Self {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            buffer: BlockBuffer::new(),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        let state = &mut self.state;
+        self.buffer
+            .update(data, |block| sha256_compress(state, block));
+    }
+
+    fn finalize(mut self) -> Self::Output {
+        let state = &mut self.state;
+        self.buffer.finish(|block| sha256_compress(state, block));
+        be_bytes(&self.state)
+    }
+}
+
+/// Longest decimal representation of a `usize`.
+const MAX_DIGITS: usize = 20;
+
+/// Writes the decimal digits of `n` at the end of `buf`, returning them.
+fn format_nonce(mut n: usize, buf: &mut [u8; MAX_DIGITS]) -> &[u8] {
+    let mut i = buf.len();
+    loop {
+        i -= 1;
+        buf[i] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    &buf[i..]
+}
+
+/// Hasher state that has already consumed the secret. Every 64-byte block of the secret is
+/// compressed once here; each nonce then only clones the state and adds its own digits.
+#[derive(Clone)]
+struct Midstate<H: Hasher>(H);
+
+impl<H: Hasher> Midstate<H> {
+    fn new(secret: &[u8]) -> Self {
+        let mut hasher = H::new();
+        hasher.update(secret);
+        Self(hasher)
+    }
+
+    fn hash(&self, n: usize, buf: &mut [u8; MAX_DIGITS]) -> H::Output {
+        let mut hasher = self.0.clone();
+        hasher.update(format_nonce(n, buf));
+        hasher.finalize()
+    }
+
+    /// First nonce of `nonces` whose digest hits `target`.
+    fn find(&self, mut nonces: impl Iterator<Item = usize>, target: &Target) -> Option<usize> {
+        let mut buf = [0; MAX_DIGITS];
+        nonces.find(|n| target.matches(self.hash(*n, &mut buf).as_ref()))
+    }
 }
 
-pub fn part2() -> io::Result<usize> {
-    let secret = "ckczppom";
-    Ok(simple_mine(&secret, &[0, 0, 0]))
+fn fast_mine<H: Hasher>(secret: &str, target: &Target) -> usize {
+    Midstate::<H>::new(secret.as_bytes())
+        .find(0.., target)
+        .expect("Ran out of nonces")
+}
+
+/// Number of nonces a worker takes at once.
+const BATCH_SIZE: usize = 10_000;
+
+/// Same result as `fast_mine`, with nonces handed out in batches to `workers` threads.
+/// Fails on targets the digest is too short to ever match.
+///
+/// Batches are handed out in increasing order and a worker only gives up when its next batch
+/// starts past the best nonce found so far, so every batch that could hold a smaller nonce is
+/// always fully searched.
+pub fn parallel_mine<H: Hasher>(
+    secret: &str,
+    target: &Target,
+    workers: usize,
+) -> Result<usize, String> {
+    target.check(H::OUTPUT_LEN)?;
+    if workers <= 1 {
+        return Ok(fast_mine::<H>(secret, target));
+    }
+    let next_batch = AtomicUsize::new(0);
+    let best = AtomicUsize::new(usize::MAX);
+    let midstate = Midstate::<H>::new(secret.as_bytes());
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let start = next_batch.fetch_add(BATCH_SIZE, Ordering::Relaxed);
+                if start >= best.load(Ordering::Relaxed) || start > usize::MAX - BATCH_SIZE {
+                    break;
+                }
+                if let Some(n) = midstate.find(start..start + BATCH_SIZE, target) {
+                    best.fetch_min(n, Ordering::Relaxed);
+                }
+            });
+        }
+    });
+    match best.into_inner() {
+        usize::MAX => Err("ran out of nonces".to_owned()),
+        n => Ok(n),
+    }
+}
+
+fn mine_input(target: &str) -> Result<usize, Box<dyn Error>> {
+    let secret = read_to_string("data/d04.txt")?;
+    let target = Target::parse(target)?;
+    Ok(parallel_mine::<Md5>(secret.trim(), &target, num_threads())?)
+}
+
+pub fn part1() -> Result<usize, Box<dyn Error>> {
+    mine_input("00000")
+}
+
+pub fn part2() -> Result<usize, Box<dyn Error>> {
+    mine_input("000000")
 }
 
 pub fn main() {
@@ -44,6 +436,184 @@ pub fn main() {
 mod tests {
     use super::*;
 
+    fn hex<H: Hasher>(data: &[u8]) -> String {
+        let mut hasher = H::new();
+        hasher.update(data);
+        hasher
+            .finalize()
+            .as_ref()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    const LONG: &[u8] = b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq";
+
+    #[test]
+    fn test_sha1() {
+        assert_eq!(hex::<Sha1>(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex::<Sha1>(b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex::<Sha1>(LONG),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            hex::<Sha256>(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex::<Sha256>(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex::<Sha256>(LONG),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_split_updates() {
+        let data = [b'x'; 200];
+        let mut hasher = Sha256::new();
+        data.chunks(7).for_each(|c| hasher.update(c));
+        assert_eq!(hasher.finalize().as_ref(), {
+            let mut whole = Sha256::new();
+            whole.update(&data);
+            whole.finalize()
+        });
+    }
+
+    #[test]
+    fn test_target_parse() {
+        assert_eq!(Target::parse("000"), Ok(Target::HexPrefix(vec![0, 0, 0])));
+        assert_eq!(
+            Target::parse("0000aB"),
+            Ok(Target::HexPrefix(vec![0, 0, 0, 0, 0xa, 0xb]))
+        );
+        assert_eq!(Target::parse("20 bits"), Ok(Target::LeadingZeroBits(20)));
+        assert!(Target::parse("").is_err());
+        assert!(Target::parse("00g").is_err());
+        assert!(Target::parse("x bits").is_err());
+    }
+
+    #[test]
+    fn test_target_matches() {
+        let five_zeros = Target::parse("00000").unwrap();
+        assert!(five_zeros.matches(&[0, 0, 0x0f, 0xff]));
+        assert!(!five_zeros.matches(&[0, 0, 0x10]));
+        assert!(Target::parse("0000ab")
+            .unwrap()
+            .matches(&[0, 0, 0xab, 0x12]));
+        assert!(!Target::parse("0000ab").unwrap().matches(&[0, 0, 0xac]));
+        assert!(!Target::parse("000").unwrap().matches(&[0]));
+
+        let bits = Target::LeadingZeroBits(12);
+        assert!(bits.matches(&[0, 0x0f]));
+        assert!(!bits.matches(&[0, 0x10]));
+        assert!(Target::LeadingZeroBits(0).matches(&[0xff]));
+        assert!(Target::LeadingZeroBits(8).matches(&[0, 0xff]));
+        assert!(!Target::LeadingZeroBits(17).matches(&[0, 0]));
+    }
+
+    #[test]
+    fn test_target_check() {
+        assert_eq!(
+            parallel_mine::<Md5>("abcdef", &Target::parse("200 bits").unwrap(), 4),
+            Err("target needs 200 bits but the digest only has 128".to_owned())
+        );
+        let long_prefix = Target::parse(&"0".repeat(41)).unwrap();
+        assert!(parallel_mine::<Sha1>("abcdef", &long_prefix, 1).is_err());
+        assert!(long_prefix.check(Sha256::OUTPUT_LEN).is_ok());
+        assert!(Target::LeadingZeroBits(128).check(Md5::OUTPUT_LEN).is_ok());
+    }
+
+    #[test]
+    fn test_format_nonce() {
+        let mut buf = [0; MAX_DIGITS];
+        for n in [0, 7, 10, 609043, usize::MAX] {
+            assert_eq!(format_nonce(n, &mut buf), n.to_string().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_midstate_hash() {
+        let mut buf = [0; MAX_DIGITS];
+        let long_secret = "x".repeat(150);
+        for secret in ["", "abcdef", &long_secret] {
+            let midstate = Midstate::<Md5>::new(secret.as_bytes());
+            for n in [0, 1, 42, 1048970] {
+                assert_eq!(midstate.hash(n, &mut buf), hash(secret.as_bytes(), n).0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_fast_mine() {
+        let target = Target::parse("000").unwrap();
+        for secret in ["abcdef", "pqrstuv", "ckczppom"] {
+            assert_eq!(
+                fast_mine::<Md5>(secret, &target),
+                simple_mine(secret, &[0, 0x0f])
+            );
+        }
+    }
+
+    #[test]
+    fn test_other_digests() {
+        let target = Target::LeadingZeroBits(12);
+        let n = fast_mine::<Sha1>("abcdef", &target);
+        let mut hasher = Sha1::new();
+        hasher.update(format!("abcdef{n}").as_bytes());
+        assert!(target.matches(&hasher.finalize()));
+        assert_eq!(parallel_mine::<Sha256>("abcdef", &target, 3), {
+            Ok(fast_mine::<Sha256>("abcdef", &target))
+        });
+    }
+
+    /// Run with `cargo test --release bench_mine -- --ignored --nocapture`.
+    #[test]
+    #[ignore = "benchmark"]
+    fn bench_mine() {
+        use std::time::Instant;
+        let start = Instant::now();
+        let simple = simple_mine("ckczppom", &[0, 0, 0x0f]);
+        let simple_time = start.elapsed();
+        let start = Instant::now();
+        let fast = fast_mine::<Md5>("ckczppom", &Target::parse("00000").unwrap());
+        let fast_time = start.elapsed();
+        assert_eq!(simple, fast);
+        println!(
+            "simple_mine: {simple_time:?}, fast_mine: {fast_time:?}, speedup: x{:.2}",
+            simple_time.as_secs_f64() / fast_time.as_secs_f64()
+        );
+    }
+
+    #[test]
+    fn test_parallel_mine() {
+        let target = Target::parse("000").unwrap();
+        for secret in ["abcdef", "pqrstuv", "ckczppom"] {
+            let expected = fast_mine::<Md5>(secret, &target);
+            for workers in [0, 1, 3, 8] {
+                assert_eq!(parallel_mine::<Md5>(secret, &target, workers), Ok(expected));
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "slow"]
+    fn test_parallel_mine_five_zeros() {
+        let target = Target::parse("00000").unwrap();
+        assert_eq!(parallel_mine::<Md5>("abcdef", &target, 4), Ok(609043));
+        assert_eq!(parallel_mine::<Md5>("pqrstuv", &target, 4), Ok(1048970));
+    }
+
     #[test]
     #[ignore = "slow"]
     fn test_simple_mine() {
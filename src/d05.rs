@@ -1,6 +1,8 @@
-use crate::common::{print_parts, read_lines};
-use ex::io;
+use crate::common::{print_parts, read_lines, read_to_string};
+use std::error::Error;
+use std::fmt;
 use std::io::BufRead;
+use std::path::Path;
 use std::{char, collections::HashMap};
 
 fn is_vowel(c: char) -> bool {
@@ -18,6 +20,7 @@ fn is_twin(c1: char, c2: char) -> bool {
     c1 == c2
 }
 
+/// Hard-coded form of [`PRESET1`].
 fn is_nice1(s: &str) -> bool {
     let mut it = s.chars();
     let mut prev = it.next().unwrap_or_default();
@@ -83,16 +86,641 @@ fn has_repeating_pair(s: &str) -> bool {
     return false;
 }
 
+/// Hard-coded form of [`PRESET2`].
 fn is_nice2(s: &str) -> bool {
     has_sandwich(s) && has_repeating_pair(&s)
 }
 
-pub fn part1() -> io::Result<usize> {
-    Ok(read_lines!("data/d05.txt")?.filter(|l| is_nice1(l)).count())
+fn has_double(s: &str) -> bool {
+    s.chars()
+        .zip(s.chars().skip(1))
+        .any(|(c1, c2)| is_twin(c1, c2))
 }
 
-pub fn part2() -> io::Result<usize> {
-    Ok(read_lines!("data/d05.txt")?.filter(|l| is_nice2(l)).count())
+/// Rules of part 1, in the rule language.
+pub const PRESET1: &str =
+    r#"count(vowels) >= 3 && has(double) && !contains("ab", "cd", "pq", "xy")"#;
+/// Rules of part 2, in the rule language.
+pub const PRESET2: &str = "has(sandwich) && has(repeating_pair)";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Ge,
+    Gt,
+}
+
+impl Cmp {
+    fn apply(&self, a: usize, b: usize) -> bool {
+        match self {
+            Self::Lt => a < b,
+            Self::Le => a <= b,
+            Self::Eq => a == b,
+            Self::Ne => a != b,
+            Self::Ge => a >= b,
+            Self::Gt => a > b,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Feature {
+    /// Two identical letters in a row, like `xx`.
+    Double,
+    /// A letter repeated with exactly one letter between, like `xyx`.
+    Sandwich,
+    /// A pair of letters appearing twice without overlapping, like `xyxy`.
+    RepeatingPair,
+}
+
+impl Feature {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "double" => Ok(Self::Double),
+            "sandwich" => Ok(Self::Sandwich),
+            "repeating_pair" => Ok(Self::RepeatingPair),
+            _ => Err(format!("unknown feature '{s}'")),
+        }
+    }
+
+    fn check(&self, s: &str) -> bool {
+        match self {
+            Self::Double => has_double(s),
+            Self::Sandwich => has_sandwich(s),
+            Self::RepeatingPair => has_repeating_pair(s),
+        }
+    }
+}
+
+/// Compiled form of a rule such as
+/// `count(vowels) >= 3 && has(double) && !contains("ab", "cd", "pq", "xy")`.
+///
+/// Grammar, `&&` binding tighter than `||`, `#` starting a comment:
+/// ```text
+/// rule  := and ("||" and)*
+/// and   := unary ("&&" unary)*
+/// unary := "!" unary | "(" rule ")" | "count(" class ")" cmp number
+///        | "has(" feature ")" | "contains(" string ("," string)* ")"
+/// class := "vowels" | string          (the string lists the letters to count)
+/// cmp   := "<" | "<=" | "==" | "!=" | ">=" | ">"
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rule {
+    Count(Vec<char>, Cmp, usize),
+    Has(Feature),
+    Contains(Vec<String>),
+    Not(Box<Rule>),
+    And(Vec<Rule>),
+    Or(Vec<Rule>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Num(usize),
+    Op(&'static str),
+}
+
+const OPERATORS: [&str; 12] = [
+    "&&", "||", "<=", ">=", "==", "!=", "<", ">", "!", "(", ")", ",",
+];
+
+/// Splits `src` into tokens, each with its 1-based line number.
+fn tokenize(src: &str) -> Result<Vec<(usize, Token)>, String> {
+    let mut tokens = vec![];
+    for (i, line) in src.lines().enumerate() {
+        let err = |e: String| format!("line {}: {e}", i + 1);
+        let mut rest = line.trim_start();
+        while let Some(c) = rest.chars().next() {
+            if c == '#' {
+                // Comment until the end of the line; a `#` inside a string is read with it
+                break;
+            }
+            let (token, len) = if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+                (Token::Op(op), op.len())
+            } else if c == '"' {
+                let end = rest[1..]
+                    .find('"')
+                    .ok_or_else(|| err("unterminated string".to_owned()))?;
+                (Token::Str(rest[1..=end].to_owned()), end + 2)
+            } else if c.is_ascii_digit() {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let n = rest[..len]
+                    .parse()
+                    .map_err(|_| err(format!("invalid number '{}'", &rest[..len])))?;
+                (Token::Num(n), len)
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                (Token::Ident(rest[..len].to_owned()), len)
+            } else {
+                return Err(err(format!("unexpected character '{c}'")));
+            };
+            tokens.push((i + 1, token));
+            rest = rest[len..].trim_start();
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn error(&self, msg: &str) -> String {
+        match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some((line, _)) => format!("line {line}: {msg}"),
+            None => format!("line 1: {msg}"),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        let found = matches!(self.tokens.get(self.pos), Some((_, Token::Op(o))) if *o == op);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{op}'")))
+        }
+    }
+
+    fn rule(&mut self) -> Result<Rule, String> {
+        let mut rules = vec![self.and()?];
+        while self.eat("||") {
+            rules.push(self.and()?);
+        }
+        Ok(if rules.len() == 1 {
+            rules.pop().unwrap()
+        } else {
+            Rule::Or(rules)
+        })
+    }
+
+    fn and(&mut self) -> Result<Rule, String> {
+        let mut rules = vec![self.unary()?];
+        while self.eat("&&") {
+            rules.push(self.unary()?);
+        }
+        Ok(if rules.len() == 1 {
+            rules.pop().unwrap()
+        } else {
+            Rule::And(rules)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Rule, String> {
+        if self.eat("!") {
+            return Ok(Rule::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let rule = self.rule()?;
+            self.expect(")")?;
+            return Ok(rule);
+        }
+        let start = self.pos;
+        let rule = match self.next() {
+            Some(Token::Ident(name)) if name == "count" => {
+                self.expect("(")?;
+                let class = match self.next() {
+                    Some(Token::Ident(c)) if c == "vowels" => "aeiou".chars().collect(),
+                    Some(Token::Str(s)) => s.chars().collect(),
+                    _ => return Err(self.error("expected 'vowels' or a string")),
+                };
+                self.expect(")")?;
+                let cmp = match self.next() {
+                    Some(Token::Op("<")) => Cmp::Lt,
+                    Some(Token::Op("<=")) => Cmp::Le,
+                    Some(Token::Op("==")) => Cmp::Eq,
+                    Some(Token::Op("!=")) => Cmp::Ne,
+                    Some(Token::Op(">=")) => Cmp::Ge,
+                    Some(Token::Op(">")) => Cmp::Gt,
+                    _ => return Err(self.error("expected a comparison")),
+                };
+                let Some(Token::Num(n)) = self.next() else {
+                    return Err(self.error("expected a number"));
+                };
+                Rule::Count(class, cmp, n)
+            }
+            Some(Token::Ident(name)) if name == "has" => {
+                self.expect("(")?;
+                let feature = match self.next() {
+                    Some(Token::Ident(f)) => Feature::parse(&f).map_err(|e| self.error(&e))?,
+                    _ => return Err(self.error("expected a feature")),
+                };
+                self.expect(")")?;
+                Rule::Has(feature)
+            }
+            Some(Token::Ident(name)) if name == "contains" => {
+                self.expect("(")?;
+                let mut needles = vec![];
+                loop {
+                    match self.next() {
+                        Some(Token::Str(s)) => needles.push(s),
+                        _ => return Err(self.error("expected a string")),
+                    }
+                    if !self.eat(",") {
+                        break;
+                    }
+                }
+                self.expect(")")?;
+                Rule::Contains(needles)
+            }
+            _ => {
+                self.pos = start;
+                return Err(self.error("expected a rule"));
+            }
+        };
+        Ok(rule)
+    }
+}
+
+impl Rule {
+    pub fn parse(src: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(src)?,
+            pos: 0,
+        };
+        let rule = parser.rule()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+        Ok(rule)
+    }
+
+    /// Built-in rule sets: `part1` and `part2`.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "part1" => Some(Self::parse(PRESET1).expect("PRESET1 is valid")),
+            "part2" => Some(Self::parse(PRESET2).expect("PRESET2 is valid")),
+            _ => None,
+        }
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::parse(&read_to_string(path)?)?)
+    }
+
+    pub fn matches(&self, s: &str) -> bool {
+        match self {
+            Self::Count(class, cmp, n) => {
+                cmp.apply(s.chars().filter(|c| class.contains(c)).count(), *n)
+            }
+            Self::Has(feature) => feature.check(s),
+            Self::Contains(needles) => needles.iter().any(|n| s.contains(n.as_str())),
+            Self::Not(rule) => !rule.matches(s),
+            Self::And(rules) => rules.iter().all(|r| r.matches(s)),
+            Self::Or(rules) => rules.iter().any(|r| r.matches(s)),
+        }
+    }
+
+    /// Evaluates the rule like [`Rule::matches`], keeping every sub-rule's result along with
+    /// what was found in `s`.
+    pub fn explain(&self, s: &str) -> Explanation {
+        let (passed, evidence, children) = match self {
+            Self::Count(class, cmp, n) => {
+                let positions = s
+                    .char_indices()
+                    .filter(|(_, c)| class.contains(c))
+                    .map(|(i, _)| i)
+                    .collect::<Vec<_>>();
+                (
+                    cmp.apply(positions.len(), *n),
+                    Some(Evidence::Positions(positions)),
+                    vec![],
+                )
+            }
+            Self::Has(feature) => {
+                let evidence = feature.find(s);
+                (evidence.is_some(), evidence, vec![])
+            }
+            Self::Contains(needles) => {
+                let evidence = find_any(s, needles);
+                (evidence.is_some(), evidence, vec![])
+            }
+            Self::Not(rule) => {
+                let child = rule.explain(s);
+                (!child.passed, None, vec![child])
+            }
+            Self::And(rules) | Self::Or(rules) => {
+                let children = rules.iter().map(|r| r.explain(s)).collect::<Vec<_>>();
+                let passed = match self {
+                    Self::And(_) => children.iter().all(|c| c.passed),
+                    _ => children.iter().any(|c| c.passed),
+                };
+                (passed, None, children)
+            }
+        };
+        Explanation {
+            rule: self.to_string(),
+            passed,
+            evidence,
+            children,
+        }
+    }
+}
+
+impl fmt::Display for Cmp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+            Self::Ge => ">=",
+            Self::Gt => ">",
+        })
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Double => "double",
+            Self::Sandwich => "sandwich",
+            Self::RepeatingPair => "repeating_pair",
+        })
+    }
+}
+
+/// Writes the rule back in the rule language.
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let write_all = |f: &mut fmt::Formatter, rules: &[Rule], sep: &str| {
+            for (i, rule) in rules.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(sep)?;
+                }
+                match rule {
+                    Self::Or(_) => write!(f, "({rule})")?,
+                    Self::And(_) if sep == " && " => write!(f, "({rule})")?,
+                    _ => write!(f, "{rule}")?,
+                }
+            }
+            Ok(())
+        };
+        match self {
+            Self::Count(class, cmp, n)
+                if class.iter().eq("aeiou".chars().collect::<Vec<_>>().iter()) =>
+            {
+                write!(f, "count(vowels) {cmp} {n}")
+            }
+            Self::Count(class, cmp, n) => {
+                write!(
+                    f,
+                    "count(\"{}\") {cmp} {n}",
+                    class.iter().collect::<String>()
+                )
+            }
+            Self::Has(feature) => write!(f, "has({feature})"),
+            Self::Contains(needles) => {
+                let needles = needles
+                    .iter()
+                    .map(|n| format!("\"{n}\""))
+                    .collect::<Vec<_>>();
+                write!(f, "contains({})", needles.join(", "))
+            }
+            Self::Not(rule) => match **rule {
+                Self::And(_) | Self::Or(_) => write!(f, "!({rule})"),
+                _ => write!(f, "!{rule}"),
+            },
+            Self::And(rules) => write_all(f, rules, " && "),
+            Self::Or(rules) => write_all(f, rules, " || "),
+        }
+    }
+}
+
+/// What a rule found in a string. Offsets are byte offsets into the string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Evidence {
+    /// Offsets of the letters counted by a `count` rule.
+    Positions(Vec<usize>),
+    /// First doubled letter.
+    Double(usize, char),
+    /// First letter repeated with one letter between, and the three letters.
+    Sandwich(usize, String),
+    /// Offsets of the first two non-overlapping occurrences of a pair.
+    RepeatingPair(usize, usize, String),
+    /// First occurrence of one of the strings of a `contains` rule.
+    Substring(usize, String),
+}
+
+impl fmt::Display for Evidence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Positions(positions) => {
+                let positions = positions.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+                write!(f, "{} found at [{}]", positions.len(), positions.join(", "))
+            }
+            Self::Double(i, c) => write!(f, "'{c}{c}' at {i}"),
+            Self::Sandwich(i, triple) => write!(f, "'{triple}' at {i}"),
+            Self::RepeatingPair(i, j, pair) => write!(f, "'{pair}' at {i} and {j}"),
+            Self::Substring(i, needle) => write!(f, "'{needle}' at {i}"),
+        }
+    }
+}
+
+/// Result of [`Rule::explain`], with one child per sub-rule.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Explanation {
+    pub rule: String,
+    pub passed: bool,
+    pub evidence: Option<Evidence>,
+    pub children: Vec<Explanation>,
+}
+
+impl Explanation {
+    fn write_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        let status = if self.passed { "pass" } else { "FAIL" };
+        write!(
+            f,
+            "{:indent$}{status} {}",
+            "",
+            self.rule,
+            indent = 2 * depth
+        )?;
+        match &self.evidence {
+            Some(evidence) => writeln!(f, ": {evidence}")?,
+            None if self.children.is_empty() => writeln!(f, ": not found")?,
+            None => writeln!(f)?,
+        }
+        self.children
+            .iter()
+            .try_for_each(|c| c.write_indented(f, depth + 1))
+    }
+}
+
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_indented(f, 0)
+    }
+}
+
+impl Feature {
+    fn find(&self, s: &str) -> Option<Evidence> {
+        let chars = s.char_indices().collect::<Vec<_>>();
+        match self {
+            Self::Double => chars
+                .windows(2)
+                .find(|w| is_twin(w[0].1, w[1].1))
+                .map(|w| Evidence::Double(w[0].0, w[0].1)),
+            Self::Sandwich => chars
+                .windows(3)
+                .find(|w| w[0].1 == w[2].1)
+                .map(|w| Evidence::Sandwich(w[0].0, w.iter().map(|(_, c)| c).collect())),
+            Self::RepeatingPair => {
+                let mut first_seen = HashMap::new();
+                chars.windows(2).enumerate().find_map(|(k, w)| {
+                    let pair = (w[0].1, w[1].1);
+                    let (first_k, first_offset) = *first_seen.entry(pair).or_insert((k, w[0].0));
+                    (k >= first_k + 2).then(|| {
+                        Evidence::RepeatingPair(
+                            first_offset,
+                            w[0].0,
+                            [pair.0, pair.1].iter().collect(),
+                        )
+                    })
+                })
+            }
+        }
+    }
+}
+
+fn find_any(s: &str, needles: &[String]) -> Option<Evidence> {
+    needles
+        .iter()
+        .filter_map(|n| s.find(n.as_str()).map(|i| (i, n)))
+        .min()
+        .map(|(i, n)| Evidence::Substring(i, n.clone()))
+}
+
+/// Whether a string is nice under each rule set.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Classification {
+    pub nice1: bool,
+    pub nice2: bool,
+}
+
+/// Evaluates both rule sets in a single pass, without allocating.
+/// Returns `None` if `s` holds anything other than lowercase ASCII letters.
+pub fn classify(s: &[u8]) -> Option<Classification> {
+    // Offset + 1 of the first occurrence of each pair, 0 if not seen yet
+    let mut first_pair = [[0usize; 26]; 26];
+    let mut vowels = 0;
+    let (mut double, mut forbidden, mut sandwich, mut repeating) = (false, false, false, false);
+    for (i, &b) in s.iter().enumerate() {
+        if !b.is_ascii_lowercase() {
+            return None;
+        }
+        if matches!(b, b'a' | b'e' | b'i' | b'o' | b'u') {
+            vowels += 1;
+        }
+        if i >= 1 {
+            let prev = s[i - 1];
+            double |= prev == b;
+            forbidden |= matches!(&[prev, b], b"ab" | b"cd" | b"pq" | b"xy");
+            let first = &mut first_pair[(prev - b'a') as usize][(b - b'a') as usize];
+            if *first == 0 {
+                *first = i;
+            } else {
+                // This pair starts at i - 1, the first one at *first - 1
+                repeating |= i >= *first + 2;
+            }
+        }
+        if i >= 2 {
+            sandwich |= s[i - 2] == b;
+        }
+    }
+    Some(Classification {
+        nice1: vowels >= 3 && double && !forbidden,
+        nice2: sandwich && repeating,
+    })
+}
+
+/// Number of nice lines of `content` under each rule set. Lines with anything other than
+/// lowercase letters take the slower path through [`is_nice1`] and [`is_nice2`].
+pub fn count_nice(content: &[u8]) -> (usize, usize) {
+    content
+        .split(|&b| b == b'\n')
+        .fold((0, 0), |(n1, n2), line| {
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            let (nice1, nice2) = match classify(line) {
+                Some(c) => (c.nice1, c.nice2),
+                None => {
+                    let line = String::from_utf8_lossy(line);
+                    (is_nice1(&line), is_nice2(&line))
+                }
+            };
+            (n1 + nice1 as usize, n2 + nice2 as usize)
+        })
+}
+
+pub fn count_nice_file<P: AsRef<Path>>(path: P) -> Result<(usize, usize), Box<dyn Error>> {
+    Ok(count_nice(read_to_string(path)?.as_bytes()))
+}
+
+const USAGE: &str = "usage: d05 explain [--rules part1|part2|FILE] (STRING | --file FILE)";
+
+/// Command line entry point: `d05 explain [--rules part1|part2|FILE] (STRING | --file FILE)`.
+/// Prints the explanation for `STRING`, or for every naughty line of `FILE`.
+pub fn cli(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let Some((command, mut args)) = args.split_first() else {
+        return Err(USAGE.into());
+    };
+    if command != "explain" {
+        return Err(format!("unknown command '{command}'\n{USAGE}").into());
+    }
+    let mut rule = Rule::preset("part1").unwrap();
+    if let [flag, name, rest @ ..] = args {
+        if flag == "--rules" {
+            rule = match Rule::preset(name) {
+                Some(rule) => rule,
+                None => Rule::load(name)?,
+            };
+            args = rest;
+        }
+    }
+    match args {
+        [flag, path] if flag == "--file" => {
+            for (i, line) in read_lines!(path)?.enumerate() {
+                let explanation = rule.explain(&line);
+                if !explanation.passed {
+                    print!("line {}: {line}\n{explanation}", i + 1);
+                }
+            }
+        }
+        [s] => print!("{}", rule.explain(s)),
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+pub fn part1() -> Result<usize, Box<dyn Error>> {
+    Ok(count_nice_file("data/d05.txt")?.0)
+}
+
+pub fn part2() -> Result<usize, Box<dyn Error>> {
+    Ok(count_nice_file("data/d05.txt")?.1)
 }
 
 pub fn main() {
@@ -102,6 +730,7 @@ pub fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::Lcg;
 
     #[test]
     fn test_is_vowel() {
@@ -172,4 +801,237 @@ mod tests {
         assert_eq!(is_nice2("uurcxstgmygtbstg"), false);
         assert_eq!(is_nice2("ieodomkazucvgmuy"), false);
     }
+
+    const SAMPLES: [&str; 9] = [
+        "ugknbfddgicrmopn",
+        "aaa",
+        "jchzalrnumimnmhp",
+        "haegwjzuvuyypxyu",
+        "dvszwmarrgswjxmb",
+        "qjhvhtzxzqqjkmpb",
+        "xxyxx",
+        "uurcxstgmygtbstg",
+        "ieodomkazucvgmuy",
+    ];
+
+    #[test]
+    fn test_presets() {
+        let (rule1, rule2) = (
+            Rule::preset("part1").unwrap(),
+            Rule::preset("part2").unwrap(),
+        );
+        for s in SAMPLES {
+            assert_eq!(rule1.matches(s), is_nice1(s), "{s}");
+            assert_eq!(rule2.matches(s), is_nice2(s), "{s}");
+        }
+        assert_eq!(Rule::preset("part3"), None);
+    }
+
+    #[test]
+    fn test_rule_parse() {
+        assert_eq!(
+            Rule::parse("!has(double) || count(\"xyz\") != 0 && has(sandwich)"),
+            Ok(Rule::Or(vec![
+                Rule::Not(Box::new(Rule::Has(Feature::Double))),
+                Rule::And(vec![
+                    Rule::Count(vec!['x', 'y', 'z'], Cmp::Ne, 0),
+                    Rule::Has(Feature::Sandwich),
+                ]),
+            ]))
+        );
+        assert_eq!(
+            Rule::parse("(has(double)) # comment"),
+            Ok(Rule::Has(Feature::Double))
+        );
+        assert_eq!(
+            Rule::parse(r##"contains("a#b", "#") # comment"##),
+            Ok(Rule::Contains(vec!["a#b".to_owned(), "#".to_owned()]))
+        );
+        assert_eq!(
+            Rule::parse("has(double) &&\nhas(triple)"),
+            Err("line 2: unknown feature 'triple'".to_owned())
+        );
+        assert_eq!(
+            Rule::parse("count(vowels) >= 3 ||"),
+            Err("line 1: expected a rule".to_owned())
+        );
+        assert_eq!(
+            Rule::parse("has(double) has(sandwich)"),
+            Err("line 1: unexpected trailing input".to_owned())
+        );
+        assert!(Rule::parse("contains(\"ab)").is_err());
+        assert!(Rule::parse("count(vowels) >= x").is_err());
+    }
+
+    #[test]
+    fn test_rule_matches() {
+        let rule = Rule::parse("count(vowels) < 2 && !contains(\"zz\", \"q\")").unwrap();
+        assert!(rule.matches("bcda"));
+        assert!(!rule.matches("bcdae"));
+        assert!(!rule.matches("bqd"));
+        assert!(!rule.matches("azzb"));
+    }
+
+    #[test]
+    fn test_rule_load() {
+        let rule = Rule::load("data/test/d05_rules.txt").unwrap();
+        assert!(rule.matches("xyxy"));
+        assert!(!rule.matches("aaa"));
+        assert!(Rule::load("data/test/missing.txt").is_err());
+    }
+
+    #[test]
+    fn test_rule_display() {
+        for src in [
+            PRESET1,
+            PRESET2,
+            "!(has(double) || count(\"xy\") < 2) && has(sandwich)",
+        ] {
+            let rule = Rule::parse(src).unwrap();
+            assert_eq!(rule.to_string(), src);
+            assert_eq!(Rule::parse(&rule.to_string()), Ok(rule));
+        }
+    }
+
+    #[test]
+    fn test_feature_find() {
+        for s in SAMPLES {
+            assert_eq!(Feature::Double.find(s).is_some(), has_double(s), "{s}");
+            assert_eq!(Feature::Sandwich.find(s).is_some(), has_sandwich(s), "{s}");
+            assert_eq!(
+                Feature::RepeatingPair.find(s).is_some(),
+                has_repeating_pair(s),
+                "{s}"
+            );
+        }
+        assert_eq!(
+            Feature::Double.find("abccdd"),
+            Some(Evidence::Double(2, 'c'))
+        );
+        assert_eq!(
+            Feature::Sandwich.find("abcdcb"),
+            Some(Evidence::Sandwich(2, "cdc".to_owned()))
+        );
+        assert_eq!(
+            Feature::RepeatingPair.find("aaaxyxy"),
+            Some(Evidence::RepeatingPair(3, 5, "xy".to_owned()))
+        );
+        assert_eq!(
+            Feature::RepeatingPair.find("aaaa"),
+            Some(Evidence::RepeatingPair(0, 2, "aa".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_explain() {
+        let rule = Rule::preset("part1").unwrap();
+        let explanation = rule.explain("haegwjzuvuyypxyu");
+        assert!(!explanation.passed);
+        assert_eq!(
+            explanation.children[0].evidence,
+            Some(Evidence::Positions(vec![1, 2, 7, 9, 15]))
+        );
+        assert_eq!(
+            explanation.children[1].evidence,
+            Some(Evidence::Double(10, 'y'))
+        );
+        assert_eq!(
+            explanation.children[2].children[0].evidence,
+            Some(Evidence::Substring(13, "xy".to_owned()))
+        );
+        assert_eq!(
+            explanation.to_string(),
+            "FAIL count(vowels) >= 3 && has(double) && !contains(\"ab\", \"cd\", \"pq\", \"xy\")
+  pass count(vowels) >= 3: 5 found at [1, 2, 7, 9, 15]
+  pass has(double): 'yy' at 10
+  FAIL !contains(\"ab\", \"cd\", \"pq\", \"xy\")
+    pass contains(\"ab\", \"cd\", \"pq\", \"xy\"): 'xy' at 13
+"
+        );
+        let explanation = Rule::preset("part2").unwrap().explain("ieodomkazucvgmuy");
+        assert_eq!(
+            explanation.to_string(),
+            "FAIL has(sandwich) && has(repeating_pair)
+  pass has(sandwich): 'odo' at 2
+  FAIL has(repeating_pair): not found
+"
+        );
+    }
+
+    #[test]
+    fn test_cli() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(cli(&args(&["explain", "aaa"])).is_ok());
+        assert!(cli(&args(&[
+            "explain",
+            "--rules",
+            "part2",
+            "--file",
+            "data/d05.txt"
+        ]))
+        .is_ok());
+        assert!(cli(&args(&["explain"])).is_err());
+        assert!(cli(&args(&["check", "aaa"])).is_err());
+        assert!(cli(&args(&[
+            "explain",
+            "--rules",
+            "data/test/missing.txt",
+            "aaa"
+        ]))
+        .is_err());
+    }
+
+    #[test]
+    fn test_classify() {
+        for s in SAMPLES {
+            let expected = Classification {
+                nice1: is_nice1(s),
+                nice2: is_nice2(s),
+            };
+            assert_eq!(classify(s.as_bytes()), Some(expected), "{s}");
+        }
+        assert_eq!(classify(b""), Some(Classification::default()));
+        assert_eq!(classify(b"aaA"), None);
+    }
+
+    /// Random strings over `alphabet`, from a fixed LCG so failures are reproducible.
+    fn generate(count: usize, seed: u64, alphabet: &[u8]) -> Vec<String> {
+        let mut rng = Lcg(seed);
+        let mut next = move |n: usize| rng.below(n);
+        (0..count)
+            .map(|_| {
+                let len = next(20);
+                (0..len)
+                    .map(|_| alphabet[next(alphabet.len())] as char)
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_classify_random() {
+        let alphabets: [&[u8]; 3] = [b"ab", b"abcdpqxy", b"abcdefghijklmnopqrstuvwxyz"];
+        for (seed, alphabet) in alphabets.iter().enumerate() {
+            for s in generate(5_000, seed as u64, alphabet) {
+                let c = classify(s.as_bytes()).unwrap();
+                assert_eq!(c.nice1, is_nice1(&s), "{s}");
+                assert_eq!(c.nice2, is_nice2(&s), "{s}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_count_nice() {
+        assert_eq!(count_nice(b"ugknbfddgicrmopn\r\nxxyxx\n"), (1, 1));
+        // Other characters are judged like before, not rejected
+        assert_eq!(count_nice(b"aaa\naei1oo\nxyx-xy\n"), (2, 1));
+        let lines = generate(1_000, 42, b"abcdexy-");
+        assert_eq!(
+            count_nice(lines.join("\n").as_bytes()),
+            (
+                lines.iter().filter(|s| is_nice1(s)).count(),
+                lines.iter().filter(|s| is_nice2(s)).count()
+            )
+        );
+    }
 }
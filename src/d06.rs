@@ -1,18 +1,19 @@
-use crate::common::{print_parts, read_lines};
-use ex::io;
+use crate::common::{parse_lines, print_parts, read_to_string, to_pbm, to_pgm};
+use ex::fs;
 use itertools::Itertools;
-use std::io::BufRead;
+use std::error::Error;
+use std::path::Path;
 
-type Point = (usize, usize);
+pub type Point = (usize, usize);
 
-#[derive(Debug, PartialEq, Eq)]
-enum Action {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
     Toggle,
     TurnOn,
     TurnOff,
 }
 
-type Command = (Action, Point, Point);
+pub type Command = (Action, Point, Point);
 
 fn parse_coords(coords_str: &str) -> Option<Point> {
     let (sx, sy) = coords_str.split_once(",")?;
@@ -34,39 +35,224 @@ fn parse_line(line: &str) -> Option<Command> {
     }
 }
 
-fn apply_command_1(state: &mut Vec<Vec<u8>>, command: &Command) {
+/// Size of the grid, and what to do with rectangles whose corners are reversed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GridConfig {
+    pub width: usize,
+    pub height: usize,
+    /// Swap reversed corners instead of rejecting the command.
+    pub normalize: bool,
+}
+
+impl Default for GridConfig {
+    fn default() -> Self {
+        Self {
+            width: 1000,
+            height: 1000,
+            normalize: false,
+        }
+    }
+}
+
+impl GridConfig {
+    fn validate(&self, command: Command) -> Result<Command, String> {
+        let (action, (x1, y1), (x2, y2)) = command;
+        for (x, y) in [(x1, y1), (x2, y2)] {
+            if x >= self.width || y >= self.height {
+                return Err(format!(
+                    "corner {x},{y} is outside the {}x{} grid",
+                    self.width, self.height
+                ));
+            }
+        }
+        if x1 <= x2 && y1 <= y2 {
+            Ok(command)
+        } else if self.normalize {
+            Ok((action, (x1.min(x2), y1.min(y2)), (x1.max(x2), y1.max(y2))))
+        } else {
+            Err(format!("reversed rectangle {x1},{y1} through {x2},{y2}"))
+        }
+    }
+}
+
+/// Parses and validates every non-empty line of `content`.
+pub fn parse_commands(content: &str, config: &GridConfig) -> Result<Vec<Command>, String> {
+    parse_lines(content, |l| {
+        parse_line(l)
+            .ok_or_else(|| format!("cannot parse instruction '{}'", l.trim()))
+            .and_then(|c| config.validate(c))
+    })
+}
+
+/// How a light reacts to each action, and how much it contributes to the total.
+pub trait LightSemantics {
+    type Cell: Copy + Default + PartialEq;
+
+    fn turn_on(&self, cell: Self::Cell) -> Self::Cell;
+    fn turn_off(&self, cell: Self::Cell) -> Self::Cell;
+    fn toggle(&self, cell: Self::Cell) -> Self::Cell;
+    fn brightness(&self, cell: Self::Cell) -> usize;
+
+    fn switch(&self, action: &Action, cell: Self::Cell) -> Self::Cell {
+        match action {
+            Action::TurnOn => self.turn_on(cell),
+            Action::TurnOff => self.turn_off(cell),
+            Action::Toggle => self.toggle(cell),
+        }
+    }
+}
+
+/// Part 1: lights are either on or off.
+pub struct Binary;
+
+impl LightSemantics for Binary {
+    type Cell = bool;
+
+    fn turn_on(&self, _: bool) -> bool {
+        true
+    }
+
+    fn turn_off(&self, _: bool) -> bool {
+        false
+    }
+
+    fn toggle(&self, cell: bool) -> bool {
+        !cell
+    }
+
+    fn brightness(&self, cell: bool) -> usize {
+        cell as usize
+    }
+}
+
+/// Part 2: turning on adds 1, toggling adds 2 and turning off removes 1 down to 0.
+pub struct Brightness;
+
+impl LightSemantics for Brightness {
+    /// Wide enough that no realistic number of commands can overflow it.
+    type Cell = u64;
+
+    fn turn_on(&self, cell: u64) -> u64 {
+        cell + 1
+    }
+
+    fn turn_off(&self, cell: u64) -> u64 {
+        cell.saturating_sub(1)
+    }
+
+    fn toggle(&self, cell: u64) -> u64 {
+        cell + 2
+    }
+
+    fn brightness(&self, cell: u64) -> usize {
+        cell as usize
+    }
+}
+
+/// Colored lights: turning on mixes in `color`, turning off halves every channel and toggling
+/// inverts them. Brightness is the sum of the channels.
+pub struct Rgb {
+    pub color: [u8; 3],
+}
+
+impl LightSemantics for Rgb {
+    type Cell = [u8; 3];
+
+    fn turn_on(&self, cell: [u8; 3]) -> [u8; 3] {
+        std::array::from_fn(|i| cell[i].saturating_add(self.color[i]))
+    }
+
+    fn turn_off(&self, cell: [u8; 3]) -> [u8; 3] {
+        cell.map(|c| c / 2)
+    }
+
+    fn toggle(&self, cell: [u8; 3]) -> [u8; 3] {
+        cell.map(|c| u8::MAX - c)
+    }
+
+    fn brightness(&self, cell: [u8; 3]) -> usize {
+        cell.iter().map(|c| *c as usize).sum()
+    }
+}
+
+/// Counters modulo `modulus`: turning on adds 1, toggling adds 2 and turning off removes 1,
+/// wrapping around in both directions.
+pub struct WrappingCounter {
+    modulus: u32,
+}
+
+impl WrappingCounter {
+    pub fn new(modulus: u32) -> Result<Self, String> {
+        if modulus == 0 {
+            return Err("modulus must be at least 1".to_owned());
+        }
+        Ok(Self { modulus })
+    }
+
+    /// `cell + delta` modulo `modulus`, in `u64` so that large moduli cannot overflow.
+    fn step(&self, cell: u32, delta: u64) -> u32 {
+        let modulus = self.modulus as u64;
+        ((cell as u64 % modulus + delta) % modulus) as u32
+    }
+}
+
+impl LightSemantics for WrappingCounter {
+    type Cell = u32;
+
+    fn turn_on(&self, cell: u32) -> u32 {
+        self.step(cell, 1)
+    }
+
+    fn turn_off(&self, cell: u32) -> u32 {
+        self.step(cell, self.modulus as u64 - 1)
+    }
+
+    fn toggle(&self, cell: u32) -> u32 {
+        self.step(cell, 2)
+    }
+
+    fn brightness(&self, cell: u32) -> usize {
+        cell as usize
+    }
+}
+
+/// Dense reference implementations.
+#[cfg(test)]
+fn apply_command_1(state: &mut [Vec<u8>], command: &Command) {
     let xi = (command.1).0;
     let xj = (command.2).0;
     let yi = (command.1).1;
     let yj = (command.2).1;
-    for x in xi..(xj + 1) {
-        for y in yi..(yj + 1) {
-            state[x][y] = match command.0 {
+    for row in &mut state[xi..=xj] {
+        for light in &mut row[yi..=yj] {
+            *light = match command.0 {
                 Action::TurnOff => 0,
                 Action::TurnOn => 1,
-                Action::Toggle => 1 - state[x][y],
+                Action::Toggle => 1 - *light,
             }
         }
     }
 }
 
-fn apply_command_2(state: &mut Vec<Vec<u8>>, command: &Command) {
+#[cfg(test)]
+fn apply_command_2(state: &mut [Vec<u8>], command: &Command) {
     let xi = (command.1).0;
     let xj = (command.2).0;
     let yi = (command.1).1;
     let yj = (command.2).1;
-    for x in xi..(xj + 1) {
-        for y in yi..(yj + 1) {
-            state[x][y] = match command.0 {
-                Action::TurnOff => state[x][y].saturating_sub(1),
-                Action::TurnOn => state[x][y] + 1,
-                Action::Toggle => state[x][y] + 2,
+    for row in &mut state[xi..=xj] {
+        for light in &mut row[yi..=yj] {
+            *light = match command.0 {
+                Action::TurnOff => light.saturating_sub(1),
+                Action::TurnOn => *light + 1,
+                Action::Toggle => *light + 2,
             }
         }
     }
 }
 
-fn get_total_brightness(state: &Vec<Vec<u8>>) -> usize {
+#[cfg(test)]
+fn get_total_brightness(state: &[Vec<u8>]) -> usize {
     state
         .iter()
         .flat_map(|row| row.iter())
@@ -74,20 +260,372 @@ fn get_total_brightness(state: &Vec<Vec<u8>>) -> usize {
         .sum::<usize>()
 }
 
-pub fn part1() -> io::Result<usize> {
-    let mut state = vec![vec![0 as u8; 1000]; 1000];
-    read_lines!("data/d06.txt")?
-        .filter_map(|l| parse_line(&l))
-        .for_each(|c| apply_command_1(&mut state, &c));
-    Ok(get_total_brightness(&state))
+/// Light grid cut along every rectangle edge of a list of commands.
+///
+/// Lights between two consecutive cuts on both axes always receive the same commands, so each
+/// such block is stored as a single cell. The number of cells only depends on the number of
+/// commands, which keeps huge grids cheap as long as there are few instructions.
+#[derive(Clone)]
+pub struct CompressedGrid<C> {
+    /// Sorted cuts along x, block `i` covers `xs[i]..xs[i + 1]`.
+    xs: Vec<usize>,
+    ys: Vec<usize>,
+    /// Blocks in row-major order, `ys.len() - 1` per row.
+    cells: Vec<C>,
+}
+
+fn cuts(size: usize, edges: impl Iterator<Item = (usize, usize)>) -> Vec<usize> {
+    let mut cuts = vec![0, size];
+    cuts.extend(edges.flat_map(|(start, end)| [start, end + 1]));
+    cuts.sort_unstable();
+    cuts.dedup();
+    cuts
+}
+
+impl<C: Copy + Default + PartialEq> CompressedGrid<C> {
+    /// Grid of `width` x `height` lights, all off, ready to run `commands`. Fails if a
+    /// command reaches outside the grid or has its corners reversed.
+    pub fn new(width: usize, height: usize, commands: &[Command]) -> Result<Self, String> {
+        let config = GridConfig {
+            width,
+            height,
+            normalize: false,
+        };
+        for (i, command) in commands.iter().enumerate() {
+            config
+                .validate(*command)
+                .map_err(|e| format!("command {i}: {e}"))?;
+        }
+        let xs = cuts(width, commands.iter().map(|c| ((c.1).0, (c.2).0)));
+        let ys = cuts(height, commands.iter().map(|c| ((c.1).1, (c.2).1)));
+        let cells = vec![C::default(); (xs.len() - 1) * (ys.len() - 1)];
+        Ok(Self { xs, ys, cells })
+    }
+
+    /// Applies `command` to every block it covers, and tells whether any light changed. It must
+    /// be one of the commands the grid was built with, which is why only [`run`], [`frames`]
+    /// and [`Timeline`] drive the grid.
+    fn apply<S: LightSemantics<Cell = C>>(&mut self, command: &Command, semantics: &S) -> bool {
+        let mut changed = false;
+        self.apply_tracked(command, semantics, |_| changed = true);
+        changed
+    }
+
+    /// Like [`CompressedGrid::apply`], calling `on_change` with the index of every block whose
+    /// lights changed.
+    fn apply_tracked<S: LightSemantics<Cell = C>>(
+        &mut self,
+        command: &Command,
+        semantics: &S,
+        mut on_change: impl FnMut(usize),
+    ) {
+        let block = |cuts: &[usize], coord: usize| {
+            cuts.binary_search(&coord)
+                .expect("command was not used to build the grid")
+        };
+        let (xi, xj) = (
+            block(&self.xs, (command.1).0),
+            block(&self.xs, (command.2).0 + 1),
+        );
+        let (yi, yj) = (
+            block(&self.ys, (command.1).1),
+            block(&self.ys, (command.2).1 + 1),
+        );
+        let row_len = self.ys.len() - 1;
+        for x in xi..xj {
+            for i in x * row_len + yi..x * row_len + yj {
+                let new = semantics.switch(&command.0, self.cells[i]);
+                if new != self.cells[i] {
+                    self.cells[i] = new;
+                    on_change(i);
+                }
+            }
+        }
+    }
+
+    /// Index of the block holding the light at `point`, `None` outside the grid.
+    fn block_at(&self, point: Point) -> Option<usize> {
+        let (x, y) = point;
+        if x >= self.xs[self.xs.len() - 1] || y >= self.ys[self.ys.len() - 1] {
+            return None;
+        }
+        let bx = self.xs.partition_point(|c| *c <= x) - 1;
+        let by = self.ys.partition_point(|c| *c <= y) - 1;
+        Some(bx * (self.ys.len() - 1) + by)
+    }
+
+    /// Sum of every light's brightness.
+    pub fn total<S: LightSemantics<Cell = C>>(&self, semantics: &S) -> usize {
+        let row_len = self.ys.len() - 1;
+        self.xs
+            .windows(2)
+            .enumerate()
+            .map(|(x, xw)| {
+                self.ys
+                    .windows(2)
+                    .zip(&self.cells[x * row_len..(x + 1) * row_len])
+                    .map(|(yw, light)| semantics.brightness(*light) * (yw[1] - yw[0]))
+                    .sum::<usize>()
+                    * (xw[1] - xw[0])
+            })
+            .sum()
+    }
+
+    /// Brightness of every light, one row per `y`. Allocates the whole grid, so only meant for
+    /// grids small enough to look at.
+    pub fn rows<S: LightSemantics<Cell = C>>(&self, semantics: &S) -> Vec<Vec<usize>> {
+        let row_len = self.ys.len() - 1;
+        let mut rows = vec![];
+        for (y, yw) in self.ys.windows(2).enumerate() {
+            let row = self
+                .xs
+                .windows(2)
+                .enumerate()
+                .flat_map(|(x, xw)| {
+                    let b = semantics.brightness(self.cells[x * row_len + y]);
+                    std::iter::repeat_n(b, xw[1] - xw[0])
+                })
+                .collect::<Vec<_>>();
+            rows.extend(std::iter::repeat_n(row, yw[1] - yw[0]));
+        }
+        rows
+    }
+
+    fn width(&self) -> usize {
+        self.xs[self.xs.len() - 1]
+    }
+
+    /// Plain (ASCII) PBM image, lit lights in white.
+    pub fn to_pbm<S: LightSemantics<Cell = C>>(&self, semantics: &S) -> String {
+        to_pbm(self.width(), &self.rows(semantics))
+    }
+
+    /// Plain (ASCII) PGM image, from black for dark lights to white for the brightest one.
+    pub fn to_pgm<S: LightSemantics<Cell = C>>(&self, semantics: &S) -> String {
+        let rows = self.rows(semantics);
+        let max = rows.iter().flatten().copied().max().unwrap_or(0);
+        to_pgm(self.width(), &rows, max)
+    }
+}
+
+/// Image format of animation frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Pbm,
+    Pgm,
+}
+
+impl ImageFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Pbm => "pbm",
+            Self::Pgm => "pgm",
+        }
+    }
+}
+
+/// Brightest any light gets while running `commands`, found on the blocks of the grid.
+fn max_brightness<S: LightSemantics>(
+    mut grid: CompressedGrid<S::Cell>,
+    commands: &[Command],
+    semantics: &S,
+) -> usize {
+    let brightest = |grid: &CompressedGrid<S::Cell>| {
+        grid.cells
+            .iter()
+            .map(|c| semantics.brightness(*c))
+            .max()
+            .unwrap_or(0)
+    };
+    let mut max = brightest(&grid);
+    for command in commands {
+        grid.apply(command, semantics);
+        max = max.max(brightest(&grid));
+    }
+    max
+}
+
+/// Images of the grid after every `every` commands, and after the last one, each rendered
+/// only when the iterator gets to it. PGM frames share the brightest light of the whole run
+/// as white, so a gray level means the same brightness in every frame.
+pub fn frames<'a, S: LightSemantics>(
+    width: usize,
+    height: usize,
+    commands: &'a [Command],
+    semantics: &'a S,
+    every: usize,
+    format: ImageFormat,
+) -> Result<impl Iterator<Item = String> + 'a, String> {
+    let every = every.max(1);
+    let mut grid = CompressedGrid::new(width, height, commands)?;
+    let max = match format {
+        ImageFormat::Pbm => 0,
+        ImageFormat::Pgm => max_brightness(grid.clone(), commands, semantics),
+    };
+    Ok(commands.iter().enumerate().filter_map(move |(i, command)| {
+        grid.apply(command, semantics);
+        if (i + 1) % every != 0 && i + 1 != commands.len() {
+            return None;
+        }
+        let rows = grid.rows(semantics);
+        Some(match format {
+            ImageFormat::Pbm => to_pbm(width, &rows),
+            ImageFormat::Pgm => to_pgm(width, &rows, max),
+        })
+    }))
+}
+
+/// Writes `frames` to `dir` as `frame_0000.<ext>`, `frame_0001.<ext>`, ..., one at a time.
+pub fn write_frames<P: AsRef<Path>>(
+    dir: P,
+    frames: impl IntoIterator<Item = String>,
+    format: ImageFormat,
+) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(&dir)?;
+    for (i, frame) in frames.into_iter().enumerate() {
+        let path = dir
+            .as_ref()
+            .join(format!("frame_{i:04}.{}", format.extension()));
+        fs::write(path, frame)?;
+    }
+    Ok(())
+}
+
+/// Runs `commands` on a `width` x `height` grid and returns the total brightness. Fails like
+/// [`CompressedGrid::new`] on commands that do not fit the grid.
+pub fn run<S: LightSemantics>(
+    width: usize,
+    height: usize,
+    commands: &[Command],
+    semantics: &S,
+) -> Result<usize, String> {
+    let mut grid = CompressedGrid::new(width, height, commands)?;
+    for command in commands {
+        grid.apply(command, semantics);
+    }
+    Ok(grid.total(semantics))
+}
+
+/// Every intermediate state of a run, answering questions about the past without replaying
+/// all the commands. The grid, and the last command that changed each block, are saved every
+/// `interval` commands; a query about the state after `k` commands replays at most
+/// `interval - 1` of them from the closest checkpoint.
+///
+/// Commands are numbered from 0, and "after `k` commands" means after commands `0..k`.
+pub struct Timeline<'a, S: LightSemantics> {
+    commands: &'a [Command],
+    semantics: S,
+    interval: usize,
+    /// Grid after `i * interval` commands.
+    checkpoints: Vec<CompressedGrid<S::Cell>>,
+    /// Last command that changed each block of the grid, for every checkpoint.
+    last_changes: Vec<Vec<Option<usize>>>,
+    /// Total brightness after `k` commands, for every `k`.
+    totals: Vec<usize>,
+    /// Whether command `i` changed any light.
+    changed: Vec<bool>,
+}
+
+impl<'a, S: LightSemantics> Timeline<'a, S> {
+    pub fn new(
+        width: usize,
+        height: usize,
+        commands: &'a [Command],
+        semantics: S,
+        interval: usize,
+    ) -> Result<Self, String> {
+        let interval = interval.max(1);
+        let mut grid = CompressedGrid::new(width, height, commands)?;
+        let mut last_change = vec![None; grid.cells.len()];
+        let mut checkpoints = vec![grid.clone()];
+        let mut last_changes = vec![last_change.clone()];
+        let mut totals = vec![0];
+        let mut changed = vec![];
+        for (i, command) in commands.iter().enumerate() {
+            let mut any = false;
+            grid.apply_tracked(command, &semantics, |block| {
+                last_change[block] = Some(i);
+                any = true;
+            });
+            changed.push(any);
+            totals.push(grid.total(&semantics));
+            if (i + 1) % interval == 0 {
+                checkpoints.push(grid.clone());
+                last_changes.push(last_change.clone());
+            }
+        }
+        Ok(Self {
+            commands,
+            semantics,
+            interval,
+            checkpoints,
+            last_changes,
+            totals,
+            changed,
+        })
+    }
+
+    /// Total brightness after `k` commands, `None` past the last one.
+    pub fn total_after(&self, k: usize) -> Option<usize> {
+        self.totals.get(k).copied()
+    }
+
+    /// Grid after `k` commands, `None` past the last one.
+    pub fn state_after(&self, k: usize) -> Option<CompressedGrid<S::Cell>> {
+        if k > self.commands.len() {
+            return None;
+        }
+        let mut grid = self.checkpoints[k / self.interval].clone();
+        for command in &self.commands[k / self.interval * self.interval..k] {
+            grid.apply(command, &self.semantics);
+        }
+        Some(grid)
+    }
+
+    /// Last of the first `k` commands that changed the light at `point`.
+    pub fn last_change(&self, point: Point, k: usize) -> Option<usize> {
+        let (x, y) = point;
+        let k = k.min(self.commands.len());
+        let checkpoint = k / self.interval;
+        let block = self.checkpoints[checkpoint].block_at(point)?;
+        let mut light = self.checkpoints[checkpoint].cells[block];
+        let mut last = self.last_changes[checkpoint][block];
+        for (i, (action, p1, p2)) in self.commands[..k]
+            .iter()
+            .enumerate()
+            .skip(checkpoint * self.interval)
+        {
+            if (p1.0..=p2.0).contains(&x) && (p1.1..=p2.1).contains(&y) {
+                let new = self.semantics.switch(action, light);
+                if new != light {
+                    last = Some(i);
+                }
+                light = new;
+            }
+        }
+        last
+    }
+
+    /// Commands that did not change any light when they ran.
+    pub fn no_effect(&self) -> Vec<usize> {
+        (0..self.changed.len())
+            .filter(|i| !self.changed[*i])
+            .collect()
+    }
 }
 
-pub fn part2() -> io::Result<usize> {
-    let mut state = vec![vec![0 as u8; 1000]; 1000];
-    read_lines!("data/d06.txt")?
-        .filter_map(|l| parse_line(&l))
-        .for_each(|c| apply_command_2(&mut state, &c));
-    Ok(get_total_brightness(&state))
+fn solve<S: LightSemantics>(semantics: &S) -> Result<usize, Box<dyn Error>> {
+    let config = GridConfig::default();
+    let commands = parse_commands(&read_to_string("data/d06.txt")?, &config)?;
+    Ok(run(config.width, config.height, &commands, semantics)?)
+}
+
+pub fn part1() -> Result<usize, Box<dyn Error>> {
+    solve(&Binary)
+}
+
+pub fn part2() -> Result<usize, Box<dyn Error>> {
+    solve(&Brightness)
 }
 
 pub fn main() {
@@ -97,6 +635,7 @@ pub fn main() {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::common::Lcg;
     #[test]
     fn test_parse_line() {
         assert_eq!(
@@ -192,4 +731,311 @@ mod tests {
         ];
         assert_eq!(get_total_brightness(&state), 10);
     }
+
+    /// Random commands inside a `size` x `size` grid, from a fixed LCG.
+    fn generate(count: usize, size: usize, seed: u64) -> Vec<Command> {
+        let mut rng = Lcg(seed);
+        let mut next = move |n: usize| rng.below(n);
+        (0..count)
+            .map(|_| {
+                let action = match next(3) {
+                    0 => Action::TurnOn,
+                    1 => Action::TurnOff,
+                    _ => Action::Toggle,
+                };
+                let (x1, x2) = (next(size), next(size));
+                let (y1, y2) = (next(size), next(size));
+                (action, (x1.min(x2), y1.min(y2)), (x1.max(x2), y1.max(y2)))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compressed_grid() {
+        for seed in 0..20 {
+            let commands = generate(30, 40, seed);
+            let mut dense_1 = vec![vec![0; 40]; 40];
+            let mut dense_2 = vec![vec![0; 40]; 40];
+            for c in &commands {
+                apply_command_1(&mut dense_1, c);
+                apply_command_2(&mut dense_2, c);
+            }
+            assert_eq!(
+                run(40, 40, &commands, &Binary).unwrap(),
+                get_total_brightness(&dense_1)
+            );
+            assert_eq!(
+                run(40, 40, &commands, &Brightness).unwrap(),
+                get_total_brightness(&dense_2)
+            );
+        }
+    }
+
+    #[test]
+    fn test_huge_grid() {
+        let commands = [
+            (Action::TurnOn, (0, 0), (999_999, 999_999)),
+            (Action::Toggle, (0, 0), (499_999, 999_999)),
+            (Action::TurnOff, (600_000, 1), (600_000, 1)),
+        ];
+        assert_eq!(
+            run(1_000_000, 1_000_000, &commands, &Binary).unwrap(),
+            500_000_000_000 - 1
+        );
+        assert_eq!(
+            run(1_000_000, 1_000_000, &commands, &Brightness).unwrap(),
+            3 * 500_000_000_000 + 500_000_000_000 - 1
+        );
+    }
+
+    #[test]
+    fn test_semantics() {
+        let rgb = Rgb {
+            color: [200, 100, 0],
+        };
+        assert_eq!(rgb.turn_on([100, 0, 0]), [255, 100, 0]);
+        assert_eq!(rgb.toggle([255, 100, 0]), [0, 155, 255]);
+        assert_eq!(rgb.turn_off([0, 155, 255]), [0, 77, 127]);
+        assert_eq!(rgb.brightness([0, 77, 127]), 204);
+
+        let counter = WrappingCounter::new(3).unwrap();
+        assert_eq!(counter.turn_off(0), 2);
+        assert_eq!(counter.toggle(2), 1);
+        assert_eq!(counter.switch(&Action::TurnOn, 2), 0);
+
+        assert!(WrappingCounter::new(0).is_err());
+        let counter = WrappingCounter::new(u32::MAX).unwrap();
+        assert_eq!(counter.turn_on(u32::MAX - 1), 0);
+        assert_eq!(counter.toggle(u32::MAX - 1), 1);
+        assert_eq!(counter.turn_off(0), u32::MAX - 1);
+        assert_eq!(WrappingCounter::new(1).unwrap().toggle(0), 0);
+    }
+
+    #[test]
+    fn test_run_other_semantics() {
+        let commands = [
+            (Action::TurnOn, (0, 0), (3, 3)),
+            (Action::Toggle, (1, 1), (2, 2)),
+            (Action::TurnOff, (0, 0), (1, 3)),
+        ];
+        let rgb = Rgb {
+            color: [10, 20, 30],
+        };
+        // x in 2..=3 keeps 6 lights at 60 and 2 toggled to 705, x in 0..=1 is halved to 30 and 351
+        assert_eq!(
+            run(4, 4, &commands, &rgb).unwrap(),
+            6 * 60 + 2 * 705 + 6 * 30 + 2 * 351
+        );
+        let counter = WrappingCounter::new(2).unwrap();
+        // Toggling adds 2, so only the first turn on and the last turn off count
+        assert_eq!(run(4, 4, &commands, &counter).unwrap(), 8);
+    }
+
+    const ANIMATION: [Command; 3] = [
+        (Action::TurnOn, (0, 0), (2, 1)),
+        (Action::Toggle, (1, 1), (3, 2)),
+        (Action::TurnOff, (0, 0), (0, 2)),
+    ];
+
+    #[test]
+    fn test_to_pbm() {
+        let mut grid = CompressedGrid::new(4, 3, &ANIMATION).unwrap();
+        for c in &ANIMATION {
+            grid.apply(c, &Binary);
+        }
+        assert_eq!(grid.to_pbm(&Binary), "P1\n4 3\n1 0 0 1\n1 1 1 0\n1 0 0 0\n");
+        assert_eq!(
+            CompressedGrid::<bool>::new(2, 1, &[])
+                .unwrap()
+                .to_pbm(&Binary),
+            "P1\n2 1\n1 1\n"
+        );
+    }
+
+    #[test]
+    fn test_to_pgm() {
+        let mut grid = CompressedGrid::new(4, 3, &ANIMATION).unwrap();
+        for c in &ANIMATION {
+            grid.apply(c, &Brightness);
+        }
+        assert_eq!(
+            grid.to_pgm(&Brightness),
+            "P2\n4 3\n3\n0 1 1 0\n0 3 3 2\n0 2 2 2\n"
+        );
+        assert_eq!(
+            CompressedGrid::<u64>::new(2, 1, &[])
+                .unwrap()
+                .to_pgm(&Brightness),
+            "P2\n2 1\n1\n0 0\n"
+        );
+    }
+
+    fn grid_pgm(commands: &[Command]) -> String {
+        let mut grid = CompressedGrid::new(4, 3, commands).unwrap();
+        for c in commands {
+            grid.apply(c, &Brightness);
+        }
+        grid.to_pgm(&Brightness)
+    }
+
+    #[test]
+    fn test_frames() {
+        assert_eq!(
+            frames(4, 3, &ANIMATION, &Binary, 2, ImageFormat::Pbm)
+                .unwrap()
+                .collect_vec(),
+            [
+                "P1\n4 3\n0 0 0 1\n0 1 1 0\n1 0 0 0\n",
+                "P1\n4 3\n1 0 0 1\n1 1 1 0\n1 0 0 0\n",
+            ]
+        );
+        let every_step = frames(4, 3, &ANIMATION, &Brightness, 1, ImageFormat::Pgm)
+            .unwrap()
+            .collect_vec();
+        assert_eq!(every_step.len(), 3);
+        // Scaled to the brightest light of the whole run, not of this frame
+        assert_eq!(every_step[0], "P2\n4 3\n3\n1 1 1 0\n1 1 1 0\n0 0 0 0\n");
+        assert_eq!(every_step[2], grid_pgm(&ANIMATION));
+        assert_eq!(
+            frames(4, 3, &ANIMATION, &Brightness, 0, ImageFormat::Pgm)
+                .unwrap()
+                .collect_vec(),
+            every_step
+        );
+    }
+
+    #[test]
+    fn test_write_frames() {
+        let dir = std::env::temp_dir().join(format!("aoc2015_d06_frames_{}", std::process::id()));
+        let images = frames(4, 3, &ANIMATION, &Binary, 1, ImageFormat::Pbm).unwrap();
+        write_frames(&dir, images, ImageFormat::Pbm).unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("frame_0002.pbm")).unwrap(),
+            frames(4, 3, &ANIMATION, &Binary, 1, ImageFormat::Pbm)
+                .unwrap()
+                .nth(2)
+                .unwrap()
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_timeline() {
+        for seed in 0..5 {
+            let commands = generate(25, 12, seed);
+            for interval in [1, 4, 100] {
+                let timeline = Timeline::new(12, 12, &commands, Brightness, interval).unwrap();
+                let mut dense = vec![vec![0; 12]; 12];
+                let mut last_change = vec![vec![None; 12]; 12];
+                for k in 0..=commands.len() {
+                    assert_eq!(timeline.total_after(k), Some(get_total_brightness(&dense)));
+                    let rows = timeline.state_after(k).unwrap().rows(&Brightness);
+                    for (x, column) in dense.iter().enumerate() {
+                        for (y, light) in column.iter().enumerate() {
+                            assert_eq!(rows[y][x], *light as usize);
+                            assert_eq!(timeline.last_change((x, y), k), last_change[x][y]);
+                        }
+                    }
+                    if let Some(command) = commands.get(k) {
+                        let before = dense.clone();
+                        apply_command_2(&mut dense, command);
+                        for x in 0..12 {
+                            for y in 0..12 {
+                                if dense[x][y] != before[x][y] {
+                                    last_change[x][y] = Some(k);
+                                }
+                            }
+                        }
+                    }
+                }
+                assert_eq!(timeline.total_after(commands.len() + 1), None);
+                assert!(timeline.state_after(commands.len() + 1).is_none());
+            }
+        }
+    }
+
+    #[test]
+    fn test_no_effect() {
+        let commands = [
+            (Action::TurnOff, (0, 0), (3, 3)),
+            (Action::TurnOn, (0, 0), (1, 1)),
+            (Action::TurnOn, (1, 1), (1, 1)),
+            (Action::TurnOff, (2, 2), (3, 3)),
+            (Action::Toggle, (0, 0), (0, 0)),
+        ];
+        let timeline = Timeline::new(4, 4, &commands, Binary, 2).unwrap();
+        assert_eq!(timeline.no_effect(), [0, 2, 3]);
+        assert_eq!(timeline.last_change((1, 1), 5), Some(1));
+        assert_eq!(timeline.last_change((0, 0), 5), Some(4));
+        assert_eq!(timeline.last_change((3, 3), 5), None);
+        assert_eq!(timeline.last_change((4, 0), 5), None);
+        assert_eq!(timeline.last_change((0, 0), 4), Some(1));
+        let timeline = Timeline::new(4, 4, &commands, Brightness, 2).unwrap();
+        assert_eq!(timeline.no_effect(), [0, 3]);
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        let config = GridConfig::default();
+        assert_eq!(
+            parse_commands(
+                "turn on 0,0 through 999,999\n\ntoggle 5,5 through 5,5\n",
+                &config
+            ),
+            Ok(vec![
+                (Action::TurnOn, (0, 0), (999, 999)),
+                (Action::Toggle, (5, 5), (5, 5)),
+            ])
+        );
+        assert_eq!(
+            parse_commands(
+                "toggle 1,1 through 2,2\nturn on 0,0 through 1000,3",
+                &config
+            ),
+            Err("line 2: corner 1000,3 is outside the 1000x1000 grid".to_owned())
+        );
+        assert_eq!(
+            parse_commands("turn of 0,0 through 1,1", &config),
+            Err("line 1: cannot parse instruction 'turn of 0,0 through 1,1'".to_owned())
+        );
+        assert_eq!(
+            parse_commands("turn off 5,0 through 1,1", &config),
+            Err("line 1: reversed rectangle 5,0 through 1,1".to_owned())
+        );
+        let config = GridConfig {
+            width: 10,
+            height: 20,
+            normalize: true,
+        };
+        assert_eq!(
+            parse_commands("turn off 5,0 through 1,19", &config),
+            Ok(vec![(Action::TurnOff, (1, 0), (5, 19))])
+        );
+        assert!(parse_commands("turn off 0,0 through 10,0", &config).is_err());
+    }
+
+    #[test]
+    fn test_brightness_does_not_overflow() {
+        let commands = vec![(Action::Toggle, (0, 0), (1, 0)); 300];
+        assert_eq!(run(2, 1, &commands, &Brightness).unwrap(), 2 * 600);
+    }
+
+    #[test]
+    fn test_engine_rejects_bad_commands() {
+        let outside = [(Action::TurnOn, (0, 0), (9, 9))];
+        assert_eq!(
+            run(4, 4, &outside, &Binary),
+            Err("command 0: corner 9,9 is outside the 4x4 grid".to_owned())
+        );
+        let reversed = [
+            (Action::TurnOn, (0, 0), (0, 0)),
+            (Action::TurnOn, (3, 3), (0, 0)),
+        ];
+        assert_eq!(
+            run(4, 4, &reversed, &Binary),
+            Err("command 1: reversed rectangle 3,3 through 0,0".to_owned())
+        );
+        assert!(Timeline::new(4, 4, &reversed, Binary, 2).is_err());
+        assert!(frames(4, 4, &outside, &Binary, 1, ImageFormat::Pbm).is_err());
+    }
 }
@@ -1,9 +1,8 @@
-use crate::common::{print_parts, read_lines};
-use ex::io;
+use crate::common::{parse_lines, print_parts, read_to_string};
 
-use std::collections::HashMap;
-use std::io::BufRead;
-use std::vec;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::path::Path;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 enum Operand {
@@ -40,30 +39,32 @@ enum Operation {
 impl Operation {
     fn compute(&self, state: &HashMap<String, u16>) -> u16 {
         match self.clone() {
-            Self::Rshift(op1, op2) => op1.get_value(state) >> op2.get_value(state),
-            Self::Lshift(op1, op2) => op1.get_value(state) << op2.get_value(state),
+            Self::Rshift(op1, op2) => op1
+                .get_value(state)
+                .checked_shr(op2.get_value(state) as u32)
+                .unwrap_or(0),
+            Self::Lshift(op1, op2) => op1
+                .get_value(state)
+                .checked_shl(op2.get_value(state) as u32)
+                .unwrap_or(0),
             Self::Or(op1, op2) => op1.get_value(state) | op2.get_value(state),
             Self::And(op1, op2) => op1.get_value(state) & op2.get_value(state),
             Self::Not(op) => !op.get_value(state),
             Self::Assign(op) => op.get_value(state),
         }
     }
-    fn operands(&self) -> Vec<Operand> {
-        match self.clone() {
-            Self::Rshift(op1, op2) => vec![op1, op2],
-            Self::Lshift(op1, op2) => vec![op1, op2],
-            Self::Or(op1, op2) => vec![op1, op2],
-            Self::And(op1, op2) => vec![op1, op2],
-            Self::Not(op) => vec![op],
-            Self::Assign(op) => vec![op],
-        }
-    }
-
-    fn wires(self) -> Vec<String> {
-        self.operands()
-            .iter()
+    fn wires(&self) -> Vec<&str> {
+        let operands = match self {
+            Self::Rshift(op1, op2)
+            | Self::Lshift(op1, op2)
+            | Self::Or(op1, op2)
+            | Self::And(op1, op2) => vec![op1, op2],
+            Self::Not(op) | Self::Assign(op) => vec![op],
+        };
+        operands
+            .into_iter()
             .filter_map(|o| match o {
-                Operand::Wire(v) => Some(v.to_owned()),
+                Operand::Wire(v) => Some(v.as_str()),
                 Operand::Signal(_) => None,
             })
             .collect()
@@ -77,22 +78,14 @@ struct Command {
 }
 
 impl Command {
-    fn is_appliable(&self, state: &HashMap<String, u16>) -> bool {
-        self.operation
-            .clone()
-            .wires()
-            .iter()
-            .all(|k| state.contains_key(k))
-    }
-
     fn apply(&self, state: &mut HashMap<String, u16>) {
         state.insert(self.target.clone(), self.operation.compute(state));
     }
 }
 
-fn parse_line(line: impl AsRef<str> + std::fmt::Display) -> Command {
-    let tokens: Vec<&str> = line.as_ref().split_whitespace().collect();
-    match tokens.as_slice() {
+fn parse_line(line: &str) -> Result<Command, String> {
+    let tokens: Vec<&str> = line.split_whitespace().collect();
+    Ok(match tokens.as_slice() {
         [op1, "LSHIFT", op2, "->", target] => Command {
             operation: Operation::Lshift(Operand::parse(op1), Operand::parse(op2)),
             target: (*target).to_owned(),
@@ -117,44 +110,353 @@ fn parse_line(line: impl AsRef<str> + std::fmt::Display) -> Command {
             operation: Operation::Assign(Operand::parse(sig)),
             target: (*target).to_owned(),
         },
-        _ => panic!("Cannot parse line: '{}' as Command", line),
+        _ => return Err(format!("cannot parse instruction '{}'", line.trim())),
+    })
+}
+
+/// Finds a cycle among the commands whose target is not in `state`. Each of them waits on at
+/// least one other such command, so following those dependencies must loop.
+fn find_cycle(
+    commands: &[Command],
+    drivers: &HashMap<&str, usize>,
+    state: &HashMap<String, u16>,
+) -> Vec<String> {
+    let Some(start) = commands.iter().find(|c| !state.contains_key(&c.target)) else {
+        return vec![];
+    };
+    let mut path: Vec<&str> = vec![];
+    let mut current = start.target.as_str();
+    while !path.contains(&current) {
+        path.push(current);
+        current = commands[drivers[current]]
+            .operation
+            .wires()
+            .into_iter()
+            .find(|w| !state.contains_key(*w))
+            .expect("an unevaluated command waits on an unevaluated wire");
     }
+    let pos = path.iter().position(|w| *w == current).unwrap();
+    let mut cycle: Vec<String> = path[pos..].iter().map(|w| w.to_string()).collect();
+    cycle.push(current.to_owned());
+    cycle
 }
 
-fn get_wire_a(commands: &[Command]) -> u16 {
-    let num_commands = commands.len();
-    let mut num_executed = 0;
-    let mut executeds = vec![false; num_commands];
+/// Signal on every wire. Commands are evaluated in topological order, each one as soon as all
+/// the wires it reads are known.
+fn evaluate(commands: &[Command]) -> Result<HashMap<String, u16>, String> {
+    let mut drivers = HashMap::<&str, usize>::new();
+    for (i, cmd) in commands.iter().enumerate() {
+        if drivers.insert(&cmd.target, i).is_some() {
+            return Err(format!("wire '{}' is driven more than once", cmd.target));
+        }
+    }
+    let mut undefined: Vec<&str> = commands
+        .iter()
+        .flat_map(|c| c.operation.wires())
+        .filter(|w| !drivers.contains_key(w))
+        .collect();
+    if !undefined.is_empty() {
+        undefined.sort_unstable();
+        undefined.dedup();
+        return Err(format!("undefined wires: {}", undefined.join(", ")));
+    }
+
+    let mut waiting: Vec<usize> = commands.iter().map(|c| c.operation.wires().len()).collect();
+    let mut readers = HashMap::<&str, Vec<usize>>::new();
+    for (i, cmd) in commands.iter().enumerate() {
+        for wire in cmd.operation.wires() {
+            readers.entry(wire).or_default().push(i);
+        }
+    }
+    let mut ready: Vec<usize> = (0..commands.len()).filter(|i| waiting[*i] == 0).collect();
     let mut state = HashMap::<String, u16>::new();
-    while num_executed != num_commands {
-        for (cmd, is_executed) in commands.iter().zip(executeds.iter_mut()) {
-            if cmd.is_appliable(&mut state) && !*is_executed {
-                cmd.apply(&mut state);
-                *is_executed = true;
-                num_executed += 1;
+    while let Some(i) = ready.pop() {
+        commands[i].apply(&mut state);
+        for &reader in readers
+            .get(commands[i].target.as_str())
+            .into_iter()
+            .flatten()
+        {
+            waiting[reader] -= 1;
+            if waiting[reader] == 0 {
+                ready.push(reader);
             }
         }
     }
-    state["a"]
+    if state.len() < commands.len() {
+        let cycle = find_cycle(commands, &drivers, &state);
+        return Err(format!("cycle between wires: {}", cycle.join(" -> ")));
+    }
+    Ok(state)
 }
 
-pub fn part1() -> io::Result<u16> {
-    let commands: Vec<Command> = Vec::from_iter(read_lines!("data/d07.txt")?.map(parse_line));
-    Ok(get_wire_a(&commands))
+/// Wiring instructions, with signals forced onto some wires.
+#[derive(Debug, Clone, Default)]
+pub struct Circuit {
+    commands: Vec<Command>,
+    overrides: HashMap<String, u16>,
 }
 
-pub fn part2() -> io::Result<u16> {
-    let b_val = part1()?;
-    let mut commands: Vec<Command> = Vec::from_iter(read_lines!("data/d07.txt")?.map(parse_line));
-    if let Some(assign_b_command) = commands.iter_mut().find(|cmd| match cmd.operation {
-        Operation::Assign(_) => cmd.target == "b",
-        _ => false,
-    }) {
-        assign_b_command.operation = Operation::Assign(Operand::Signal(b_val));
+impl Circuit {
+    /// Parses one command per non-empty line. Fails if a wire is driven by more than one
+    /// command, so that [`Circuit::evaluate`] and [`Circuit::wire`] agree on every circuit.
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut targets = HashSet::new();
+        let commands = parse_lines(content, |l| {
+            let command = parse_line(l)?;
+            if !targets.insert(command.target.clone()) {
+                return Err(format!(
+                    "wire '{}' is driven more than once",
+                    command.target
+                ));
+            }
+            Ok(command)
+        })?;
+        Ok(Self {
+            commands,
+            overrides: HashMap::new(),
+        })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error>> {
+        Ok(Self::parse(&read_to_string(path)?)?)
+    }
+
+    /// Forces `signal` onto `wire`, replacing whatever drove it.
+    pub fn set(&mut self, wire: &str, signal: u16) -> &mut Self {
+        self.overrides.insert(wire.to_owned(), signal);
+        self
+    }
+
+    /// Commands with the overridden wires driven by their forced signal.
+    fn effective_commands(&self) -> Vec<Command> {
+        let mut commands: Vec<Command> = self
+            .commands
+            .iter()
+            .filter(|c| !self.overrides.contains_key(&c.target))
+            .cloned()
+            .collect();
+        commands.extend(self.overrides.iter().map(|(wire, signal)| Command {
+            operation: Operation::Assign(Operand::Signal(*signal)),
+            target: wire.clone(),
+        }));
+        commands
+    }
+
+    /// Signal on every wire.
+    pub fn evaluate(&self) -> Result<HashMap<String, u16>, String> {
+        evaluate(&self.effective_commands())
+    }
+
+    /// Signal on `wire`. Only the commands `wire` depends on are evaluated, so problems in
+    /// unrelated parts of the circuit do not matter.
+    pub fn wire(&self, wire: &str) -> Result<u16, String> {
+        let commands = self.effective_commands();
+        let drivers: HashMap<&str, &Command> =
+            commands.iter().map(|c| (c.target.as_str(), c)).collect();
+        if !drivers.contains_key(wire) {
+            return Err(format!("undefined wires: {wire}"));
+        }
+        let mut needed = vec![];
+        let mut seen = HashSet::from([wire]);
+        let mut stack = vec![wire];
+        while let Some(current) = stack.pop() {
+            let Some(&command) = drivers.get(current) else {
+                // Reported as undefined by `evaluate`
+                continue;
+            };
+            needed.push(command.clone());
+            for w in command.operation.wires() {
+                if seen.insert(w) {
+                    stack.push(w);
+                }
+            }
+        }
+        Ok(evaluate(&needed)?[wire])
     }
-    Ok(get_wire_a(&commands))
+}
+
+const USAGE: &str = "usage: d07 eval [--file FILE] [--set WIRE=SIGNAL]... [WIRE]...";
+
+/// Command line entry point: `d07 eval [--file FILE] [--set WIRE=SIGNAL]... [WIRE]...`.
+/// Prints the signal on each `WIRE`, or on every wire if none is given.
+pub fn cli(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let Some((command, mut args)) = args.split_first() else {
+        return Err(USAGE.into());
+    };
+    if command != "eval" {
+        return Err(format!("unknown command '{command}'\n{USAGE}").into());
+    }
+    let mut path = "data/d07.txt";
+    let mut overrides = vec![];
+    let mut wires = vec![];
+    // Flags may come before or after the wires; all of them are read before anything runs
+    loop {
+        match args {
+            [flag, file, rest @ ..] if flag == "--file" => {
+                path = file;
+                args = rest;
+            }
+            [flag, assignment, rest @ ..] if flag == "--set" => {
+                let (wire, signal) = assignment
+                    .split_once('=')
+                    .ok_or(format!("expected WIRE=SIGNAL, got '{assignment}'"))?;
+                let signal = signal
+                    .parse::<u16>()
+                    .map_err(|_| format!("invalid signal '{signal}'"))?;
+                overrides.push((wire, signal));
+                args = rest;
+            }
+            [flag, ..] if flag.starts_with("--") => return Err(USAGE.into()),
+            [wire, rest @ ..] => {
+                wires.push(wire);
+                args = rest;
+            }
+            [] => break,
+        }
+    }
+    let mut circuit = Circuit::load(path)?;
+    for (wire, signal) in overrides {
+        circuit.set(wire, signal);
+    }
+    if wires.is_empty() {
+        let state = circuit.evaluate()?;
+        let mut wires: Vec<_> = state.iter().collect();
+        wires.sort();
+        wires.iter().for_each(|(w, s)| println!("{w}: {s}"));
+    } else {
+        let signals = wires
+            .iter()
+            .map(|wire| circuit.wire(wire))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (wire, signal) in wires.iter().zip(signals) {
+            println!("{wire}: {signal}");
+        }
+    }
+    Ok(())
+}
+
+pub fn part1() -> Result<u16, Box<dyn Error>> {
+    Ok(Circuit::load("data/d07.txt")?.wire("a")?)
+}
+
+pub fn part2() -> Result<u16, Box<dyn Error>> {
+    let mut circuit = Circuit::load("data/d07.txt")?;
+    let a = circuit.wire("a")?;
+    Ok(circuit.set("b", a).wire("a")?)
 }
 
 pub fn main() {
     print_parts(7, part1(), part2());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Vec<Command> {
+        content.lines().map(|l| parse_line(l).unwrap()).collect()
+    }
+
+    fn circuit(content: &str) -> Circuit {
+        Circuit::parse(content).unwrap()
+    }
+
+    const EXAMPLE: &str = "123 -> x
+456 -> y
+x AND y -> d
+x OR y -> e
+x LSHIFT 2 -> f
+y RSHIFT 2 -> g
+NOT x -> h
+NOT y -> i";
+
+    #[test]
+    fn test_evaluate() {
+        let state = evaluate(&parse(EXAMPLE)).unwrap();
+        let expected = [
+            ("d", 72),
+            ("e", 507),
+            ("f", 492),
+            ("g", 114),
+            ("h", 65412),
+            ("i", 65079),
+            ("x", 123),
+            ("y", 456),
+        ];
+        assert_eq!(state.len(), expected.len());
+        for (wire, signal) in expected {
+            assert_eq!(state[wire], signal, "{wire}");
+        }
+        // Order of the commands does not matter
+        let reversed = parse(EXAMPLE).into_iter().rev().collect::<Vec<_>>();
+        assert_eq!(evaluate(&reversed), Ok(state));
+        assert_eq!(circuit("x -> a\n3 -> x").wire("a"), Ok(3));
+        assert_eq!(circuit("1 LSHIFT 16 -> a").wire("a"), Ok(0));
+    }
+
+    #[test]
+    fn test_evaluate_errors() {
+        assert_eq!(
+            evaluate(&parse("x AND z -> a\nw -> b\n1 -> x")),
+            Err("undefined wires: w, z".to_owned())
+        );
+        assert_eq!(
+            evaluate(&parse("c -> a\na OR 1 -> b\nb -> c\nb -> d\n1 -> e")),
+            Err("cycle between wires: a -> c -> b -> a".to_owned())
+        );
+        assert_eq!(
+            evaluate(&parse("d -> a\nb AND a -> b\nb -> d")),
+            Err("cycle between wires: b -> b".to_owned())
+        );
+        assert_eq!(
+            evaluate(&parse("1 -> a\n2 -> a")),
+            Err("wire 'a' is driven more than once".to_owned())
+        );
+        assert_eq!(
+            circuit("1 -> b").wire("a"),
+            Err("undefined wires: a".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_circuit_parse() {
+        assert_eq!(circuit(EXAMPLE).commands, parse(EXAMPLE));
+        assert_eq!(
+            Circuit::parse("1 -> a\n\nx XOR y -> z").unwrap_err(),
+            "line 3: cannot parse instruction 'x XOR y -> z'"
+        );
+        assert_eq!(
+            Circuit::parse("1 -> a\n2 -> a").unwrap_err(),
+            "line 2: wire 'a' is driven more than once"
+        );
+    }
+
+    #[test]
+    fn test_circuit_wire() {
+        let mut c = circuit("x AND y -> d\n123 -> x\n456 -> y\nq -> r\nr -> q");
+        assert_eq!(c.wire("d"), Ok(72));
+        assert!(c.evaluate().is_err());
+        c.set("y", 1).set("y", 0xffff);
+        assert_eq!(c.wire("d"), Ok(123));
+        // Overriding a wire cuts the cycle it was in
+        c.set("r", 7);
+        let state = c.evaluate().unwrap();
+        assert_eq!((state["d"], state["q"], state["r"]), (123, 7, 7));
+        assert_eq!(c.set("new", 1).wire("new"), Ok(1));
+    }
+
+    #[test]
+    fn test_cli() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert!(cli(&args(&["eval", "--set", "b=956", "a", "b"])).is_ok());
+        assert!(cli(&args(&["eval", "--set", "b=x", "a"])).is_err());
+        assert!(cli(&args(&["eval", "--set", "b", "a"])).is_err());
+        assert!(cli(&args(&["eval", "a", "--set", "b=956"])).is_ok());
+        assert!(cli(&args(&["eval", "a", "--set", "b=x"])).is_err());
+        assert!(cli(&args(&["eval", "a", "missing"])).is_err());
+        assert!(cli(&args(&["eval", "--file", "data/test/missing.txt"])).is_err());
+        assert!(cli(&args(&["eval", "--verbose"])).is_err());
+        assert!(cli(&args(&["run"])).is_err());
+    }
+}
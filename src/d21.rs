@@ -0,0 +1,225 @@
+use crate::common::{parse_stat, print_parts, read_to_string};
+use itertools::Itertools;
+use std::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Item {
+    name: &'static str,
+    cost: u32,
+    damage: u32,
+    armor: u32,
+}
+
+const fn item(name: &'static str, cost: u32, damage: u32, armor: u32) -> Item {
+    Item {
+        name,
+        cost,
+        damage,
+        armor,
+    }
+}
+
+#[derive(Debug)]
+struct Shop {
+    weapons: Vec<Item>,
+    armors: Vec<Item>,
+    rings: Vec<Item>,
+}
+
+impl Default for Shop {
+    fn default() -> Self {
+        Self {
+            weapons: vec![
+                item("Dagger", 8, 4, 0),
+                item("Shortsword", 10, 5, 0),
+                item("Warhammer", 25, 6, 0),
+                item("Longsword", 40, 7, 0),
+                item("Greataxe", 74, 8, 0),
+            ],
+            armors: vec![
+                item("Leather", 13, 0, 1),
+                item("Chainmail", 31, 0, 2),
+                item("Splintmail", 53, 0, 3),
+                item("Bandedmail", 75, 0, 4),
+                item("Platemail", 102, 0, 5),
+            ],
+            rings: vec![
+                item("Damage +1", 25, 1, 0),
+                item("Damage +2", 50, 2, 0),
+                item("Damage +3", 100, 3, 0),
+                item("Defense +1", 20, 0, 1),
+                item("Defense +2", 40, 0, 2),
+                item("Defense +3", 80, 0, 3),
+            ],
+        }
+    }
+}
+
+impl Shop {
+    /// Every legal loadout: exactly 1 weapon, 0 or 1 armor and 0 to 2 distinct rings.
+    fn loadouts(&self) -> impl Iterator<Item = Vec<Item>> + '_ {
+        let armors = std::iter::once(None).chain(self.armors.iter().map(Some));
+        let rings = (0..=2).flat_map(move |k| self.rings.iter().combinations(k));
+        self.weapons
+            .iter()
+            .cartesian_product(armors.collect_vec())
+            .cartesian_product(rings.collect_vec())
+            .map(|((weapon, armor), rings)| {
+                let mut loadout = vec![*weapon];
+                loadout.extend(armor.copied());
+                loadout.extend(rings.into_iter().copied());
+                loadout
+            })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fighter {
+    hp: u32,
+    damage: u32,
+    armor: u32,
+}
+
+impl Fighter {
+    fn equip(hp: u32, loadout: &[Item]) -> Self {
+        Self {
+            hp,
+            damage: loadout.iter().map(|i| i.damage).sum(),
+            armor: loadout.iter().map(|i| i.armor).sum(),
+        }
+    }
+
+    fn hit(&self, other: &mut Fighter) {
+        let dealt = self.damage.saturating_sub(other.armor).max(1);
+        other.hp = other.hp.saturating_sub(dealt);
+    }
+}
+
+fn parse_boss(content: &str) -> Option<Fighter> {
+    Some(Fighter {
+        hp: parse_stat(content, "Hit Points:")?,
+        damage: parse_stat(content, "Damage:")?,
+        armor: parse_stat(content, "Armor:")?,
+    })
+}
+
+/// Runs the fight turn by turn, the player striking first. Returns `true` if the player wins.
+fn player_wins(player: &Fighter, boss: &Fighter) -> bool {
+    let (mut player, mut boss) = (*player, *boss);
+    loop {
+        player.hit(&mut boss);
+        if boss.hp == 0 {
+            return true;
+        }
+        boss.hit(&mut player);
+        if player.hp == 0 {
+            return false;
+        }
+    }
+}
+
+fn cost(loadout: &[Item]) -> u32 {
+    loadout.iter().map(|i| i.cost).sum()
+}
+
+/// Cost of the cheapest loadout that beats `boss`, with that loadout.
+fn cheapest_win(shop: &Shop, hp: u32, boss: &Fighter) -> Option<(u32, Vec<Item>)> {
+    shop.loadouts()
+        .filter(|l| player_wins(&Fighter::equip(hp, l), boss))
+        .map(|l| (cost(&l), l))
+        .min_by_key(|(cost, _)| *cost)
+}
+
+/// Cost of the priciest loadout that loses to `boss`, with that loadout.
+fn priciest_loss(shop: &Shop, hp: u32, boss: &Fighter) -> Option<(u32, Vec<Item>)> {
+    shop.loadouts()
+        .filter(|l| !player_wins(&Fighter::equip(hp, l), boss))
+        .map(|l| (cost(&l), l))
+        .max_by_key(|(cost, _)| *cost)
+}
+
+fn read_boss() -> Result<Fighter, Box<dyn Error>> {
+    let content = read_to_string("data/d21.txt")?;
+    Ok(parse_boss(&content).ok_or("Cannot parse boss stats")?)
+}
+
+pub fn part1() -> Result<u32, Box<dyn Error>> {
+    let boss = read_boss()?;
+    let (cost, _) = cheapest_win(&Shop::default(), 100, &boss).ok_or("No winning loadout")?;
+    Ok(cost)
+}
+
+pub fn part2() -> Result<u32, Box<dyn Error>> {
+    let boss = read_boss()?;
+    let (cost, _) = priciest_loss(&Shop::default(), 100, &boss).ok_or("No losing loadout")?;
+    Ok(cost)
+}
+
+pub fn main() {
+    print_parts(21, part1(), part2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_boss() {
+        assert_eq!(
+            parse_boss("Hit Points: 12\nDamage: 7\nArmor: 2\n"),
+            Some(Fighter {
+                hp: 12,
+                damage: 7,
+                armor: 2
+            })
+        );
+        assert_eq!(parse_boss("Hit Points: 12\nDamage: 7\n"), None);
+    }
+
+    #[test]
+    fn test_player_wins() {
+        let player = Fighter {
+            hp: 8,
+            damage: 5,
+            armor: 5,
+        };
+        let boss = Fighter {
+            hp: 12,
+            damage: 7,
+            armor: 2,
+        };
+        assert!(player_wins(&player, &boss));
+        assert!(!player_wins(&Fighter { hp: 2, ..player }, &boss));
+    }
+
+    #[test]
+    fn test_loadouts() {
+        let shop = Shop::default();
+        // 5 weapons * 6 armor choices * (1 + 6 + 15) ring choices
+        assert_eq!(shop.loadouts().count(), 660);
+        assert!(shop.loadouts().all(|l| (1..=4).contains(&l.len())));
+    }
+
+    #[test]
+    fn test_cheapest_win() {
+        let shop = Shop::default();
+        let boss = Fighter {
+            hp: 1,
+            damage: 0,
+            armor: 0,
+        };
+        assert_eq!(
+            cheapest_win(&shop, 100, &boss),
+            Some((8, vec![item("Dagger", 8, 4, 0)]))
+        );
+        assert_eq!(priciest_loss(&shop, 100, &boss), None);
+        let boss = Fighter {
+            hp: 100,
+            damage: 8,
+            armor: 2,
+        };
+        let (cost, loadout) = priciest_loss(&shop, 100, &boss).unwrap();
+        assert_eq!(cost, super::cost(&loadout));
+        assert!(!player_wins(&Fighter::equip(100, &loadout), &boss));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::common::{parse_stat, print_parts, read_to_string};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Spell {
+    MagicMissile,
+    Drain,
+    Shield,
+    Poison,
+    Recharge,
+}
+
+const SPELLS: [Spell; 5] = [
+    Spell::MagicMissile,
+    Spell::Drain,
+    Spell::Shield,
+    Spell::Poison,
+    Spell::Recharge,
+];
+
+impl Spell {
+    fn cost(&self) -> u32 {
+        match self {
+            Self::MagicMissile => 53,
+            Self::Drain => 73,
+            Self::Shield => 113,
+            Self::Poison => 173,
+            Self::Recharge => 229,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Boss {
+    hp: u32,
+    damage: u32,
+}
+
+fn parse_boss(content: &str) -> Option<Boss> {
+    Some(Boss {
+        hp: parse_stat(content, "Hit Points:")?,
+        damage: parse_stat(content, "Damage:")?,
+    })
+}
+
+/// Game state at the moment the player has to pick a spell.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct State {
+    hp: u32,
+    mana: u32,
+    boss_hp: u32,
+    shield: u8,
+    poison: u8,
+    recharge: u8,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    Won,
+    Lost,
+    Ongoing(State),
+}
+
+impl State {
+    fn new(hp: u32, mana: u32, boss: &Boss) -> Self {
+        Self {
+            hp,
+            mana,
+            boss_hp: boss.hp,
+            shield: 0,
+            poison: 0,
+            recharge: 0,
+        }
+    }
+
+    fn armor(&self) -> u32 {
+        match self.shield {
+            0 => 0,
+            _ => 7,
+        }
+    }
+
+    fn apply_effects(&mut self) {
+        if self.poison > 0 {
+            self.boss_hp = self.boss_hp.saturating_sub(3);
+        }
+        if self.recharge > 0 {
+            self.mana += 101;
+        }
+        self.shield = self.shield.saturating_sub(1);
+        self.poison = self.poison.saturating_sub(1);
+        self.recharge = self.recharge.saturating_sub(1);
+    }
+
+    /// Start of the player's turn, before a spell is chosen.
+    fn start_turn(mut self, hard: bool) -> Outcome {
+        if hard {
+            self.hp = self.hp.saturating_sub(1);
+            if self.hp == 0 {
+                return Outcome::Lost;
+            }
+        }
+        self.apply_effects();
+        match self.boss_hp {
+            0 => Outcome::Won,
+            _ => Outcome::Ongoing(self),
+        }
+    }
+
+    /// Casts `spell`, plays the boss turn and the start of the next player turn.
+    /// Returns `None` if the spell cannot be cast.
+    fn cast(mut self, spell: Spell, boss: &Boss, hard: bool) -> Option<Outcome> {
+        if spell.cost() > self.mana {
+            return None;
+        }
+        self.mana -= spell.cost();
+        match spell {
+            Spell::MagicMissile => self.boss_hp = self.boss_hp.saturating_sub(4),
+            Spell::Drain => {
+                self.boss_hp = self.boss_hp.saturating_sub(2);
+                self.hp += 2;
+            }
+            Spell::Shield if self.shield == 0 => self.shield = 6,
+            Spell::Poison if self.poison == 0 => self.poison = 6,
+            Spell::Recharge if self.recharge == 0 => self.recharge = 5,
+            _ => return None,
+        }
+        if self.boss_hp == 0 {
+            return Some(Outcome::Won);
+        }
+
+        self.apply_effects();
+        if self.boss_hp == 0 {
+            return Some(Outcome::Won);
+        }
+        self.hp = self
+            .hp
+            .saturating_sub(boss.damage.saturating_sub(self.armor()).max(1));
+        if self.hp == 0 {
+            return Some(Outcome::Lost);
+        }
+
+        Some(self.start_turn(hard))
+    }
+}
+
+/// Best-first search over game states, ordered by mana spent.
+/// Returns the minimum mana needed to win and the spells cast to get there.
+fn least_mana_win(hp: u32, mana: u32, boss: &Boss, hard: bool) -> Option<(u32, Vec<Spell>)> {
+    let start = match State::new(hp, mana, boss).start_turn(hard) {
+        Outcome::Ongoing(state) => state,
+        Outcome::Won => return Some((0, vec![])),
+        Outcome::Lost => return None,
+    };
+    let mut seen = HashSet::<State>::new();
+    let mut queue = BinaryHeap::from([Reverse((0, Some(start), vec![]))]);
+    while let Some(Reverse((spent, state, spells))) = queue.pop() {
+        match state {
+            None => return Some((spent, spells)),
+            Some(state) => {
+                if !seen.insert(state) {
+                    continue;
+                }
+                for spell in SPELLS {
+                    let next = match state.cast(spell, boss, hard) {
+                        Some(Outcome::Won) => None,
+                        Some(Outcome::Ongoing(next)) => Some(next),
+                        Some(Outcome::Lost) | None => continue,
+                    };
+                    let mut next_spells = spells.clone();
+                    next_spells.push(spell);
+                    queue.push(Reverse((spent + spell.cost(), next, next_spells)));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Replays a spell sequence until it runs out or the fight ends.
+/// Returns the outcome at that point, or `None` if a spell could not be cast.
+fn replay(hp: u32, mana: u32, boss: &Boss, hard: bool, spells: &[Spell]) -> Option<Outcome> {
+    let mut outcome = State::new(hp, mana, boss).start_turn(hard);
+    for spell in spells {
+        outcome = match outcome {
+            Outcome::Ongoing(state) => state.cast(*spell, boss, hard)?,
+            _ => break,
+        };
+    }
+    Some(outcome)
+}
+
+fn read_boss() -> Result<Boss, Box<dyn Error>> {
+    let content = read_to_string("data/d22.txt")?;
+    Ok(parse_boss(&content).ok_or("Cannot parse boss stats")?)
+}
+
+pub fn part1() -> Result<u32, Box<dyn Error>> {
+    let boss = read_boss()?;
+    let (spent, spells) = least_mana_win(50, 500, &boss, false).ok_or("Cannot win")?;
+    debug_assert_eq!(replay(50, 500, &boss, false, &spells), Some(Outcome::Won));
+    Ok(spent)
+}
+
+pub fn part2() -> Result<u32, Box<dyn Error>> {
+    let boss = read_boss()?;
+    let (spent, spells) = least_mana_win(50, 500, &boss, true).ok_or("Cannot win")?;
+    debug_assert_eq!(replay(50, 500, &boss, true, &spells), Some(Outcome::Won));
+    Ok(spent)
+}
+
+pub fn main() {
+    print_parts(22, part1(), part2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_boss() {
+        assert_eq!(
+            parse_boss("Hit Points: 13\nDamage: 8\n"),
+            Some(Boss { hp: 13, damage: 8 })
+        );
+        assert_eq!(parse_boss("Damage: 8\n"), None);
+    }
+
+    #[test]
+    fn test_replay() {
+        let boss = Boss { hp: 13, damage: 8 };
+        let spells = [Spell::Poison, Spell::MagicMissile];
+        assert_eq!(replay(10, 250, &boss, false, &spells), Some(Outcome::Won));
+
+        let boss = Boss { hp: 14, damage: 8 };
+        let spells = [
+            Spell::Recharge,
+            Spell::Shield,
+            Spell::Drain,
+            Spell::Poison,
+            Spell::MagicMissile,
+        ];
+        assert_eq!(replay(10, 250, &boss, false, &spells), Some(Outcome::Won));
+        assert_eq!(replay(10, 250, &boss, true, &spells), Some(Outcome::Lost));
+        assert_eq!(replay(10, 250, &boss, false, &[Spell::Shield; 2]), None);
+        // Hard mode with no hit points left loses instead of wrapping around
+        assert_eq!(replay(0, 250, &boss, true, &[]), Some(Outcome::Lost));
+    }
+
+    #[test]
+    fn test_least_mana_win() {
+        let boss = Boss { hp: 13, damage: 8 };
+        assert_eq!(
+            least_mana_win(10, 250, &boss, false),
+            Some((226, vec![Spell::Poison, Spell::MagicMissile]))
+        );
+        let boss = Boss { hp: 14, damage: 8 };
+        let (spent, spells) = least_mana_win(10, 250, &boss, false).unwrap();
+        assert_eq!(spent, spells.iter().map(Spell::cost).sum::<u32>());
+        assert_eq!(replay(10, 250, &boss, false, &spells), Some(Outcome::Won));
+    }
+}
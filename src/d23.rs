@@ -0,0 +1,217 @@
+use crate::common::{parse_lines, print_parts, read_to_string};
+use std::error::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    A,
+    B,
+}
+
+impl Register {
+    fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "a" => Ok(Self::A),
+            "b" => Ok(Self::B),
+            _ => Err(format!("unknown register '{s}'")),
+        }
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            Self::A => 0,
+            Self::B => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    Hlf(Register),
+    Tpl(Register),
+    Inc(Register),
+    Jmp(isize),
+    Jie(Register, isize),
+    Jio(Register, isize),
+}
+
+fn parse_offset(s: &str) -> Result<isize, String> {
+    s.parse::<isize>()
+        .map_err(|_| format!("invalid offset '{s}'"))
+}
+
+fn parse_line(line: &str) -> Result<Instruction, String> {
+    let tokens: Vec<&str> = line.split([' ', ',']).filter(|t| !t.is_empty()).collect();
+    match tokens.as_slice() {
+        ["hlf", r] => Ok(Instruction::Hlf(Register::parse(r)?)),
+        ["tpl", r] => Ok(Instruction::Tpl(Register::parse(r)?)),
+        ["inc", r] => Ok(Instruction::Inc(Register::parse(r)?)),
+        ["jmp", o] => Ok(Instruction::Jmp(parse_offset(o)?)),
+        ["jie", r, o] => Ok(Instruction::Jie(Register::parse(r)?, parse_offset(o)?)),
+        ["jio", r, o] => Ok(Instruction::Jio(Register::parse(r)?, parse_offset(o)?)),
+        _ => Err(format!("cannot parse instruction '{line}'")),
+    }
+}
+
+pub fn parse_program(content: &str) -> Result<Vec<Instruction>, String> {
+    parse_lines(content, |l| parse_line(l.trim()))
+}
+
+/// One executed instruction, with the registers as they were before it ran.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceStep {
+    pub pc: usize,
+    pub instruction: Instruction,
+    pub regs: [u64; 2],
+}
+
+#[derive(Debug)]
+pub struct Vm {
+    regs: [u64; 2],
+    pc: isize,
+    steps: usize,
+    max_steps: usize,
+    trace: Option<Vec<TraceStep>>,
+}
+
+impl Vm {
+    pub fn new(a: u64, b: u64, max_steps: usize) -> Self {
+        Self {
+            regs: [a, b],
+            pc: 0,
+            steps: 0,
+            max_steps,
+            trace: None,
+        }
+    }
+
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(vec![]);
+        self
+    }
+
+    pub fn trace(&self) -> Option<&[TraceStep]> {
+        self.trace.as_deref()
+    }
+
+    fn reg(&self, r: Register) -> u64 {
+        self.regs[r.index()]
+    }
+
+    /// Executes the instruction at `pc`. Returns `Ok(false)` once `pc` leaves the program.
+    pub fn step(&mut self, program: &[Instruction]) -> Result<bool, String> {
+        let pc = match usize::try_from(self.pc) {
+            Ok(pc) if pc < program.len() => pc,
+            _ => return Ok(false),
+        };
+        if self.steps >= self.max_steps {
+            return Err(format!("step limit of {} reached", self.max_steps));
+        }
+        let instruction = program[pc];
+        if let Some(trace) = self.trace.as_mut() {
+            trace.push(TraceStep {
+                pc,
+                instruction,
+                regs: self.regs,
+            });
+        }
+        let offset = match instruction {
+            Instruction::Hlf(r) => {
+                self.regs[r.index()] /= 2;
+                1
+            }
+            Instruction::Tpl(r) => {
+                self.regs[r.index()] = self
+                    .reg(r)
+                    .checked_mul(3)
+                    .ok_or(format!("register overflow at line {}", pc + 1))?;
+                1
+            }
+            Instruction::Inc(r) => {
+                self.regs[r.index()] = self
+                    .reg(r)
+                    .checked_add(1)
+                    .ok_or(format!("register overflow at line {}", pc + 1))?;
+                1
+            }
+            Instruction::Jmp(o) => o,
+            Instruction::Jie(r, o) if self.reg(r).is_multiple_of(2) => o,
+            Instruction::Jio(r, o) if self.reg(r) == 1 => o,
+            Instruction::Jie(..) | Instruction::Jio(..) => 1,
+        };
+        self.pc += offset;
+        self.steps += 1;
+        Ok(true)
+    }
+
+    pub fn run(&mut self, program: &[Instruction]) -> Result<[u64; 2], String> {
+        while self.step(program)? {}
+        Ok(self.regs)
+    }
+}
+
+const MAX_STEPS: usize = 1_000_000;
+
+fn run_program(a: u64) -> Result<u64, Box<dyn Error>> {
+    let program = parse_program(&read_to_string("data/d23.txt")?)?;
+    let [_, b] = Vm::new(a, 0, MAX_STEPS).run(&program)?;
+    Ok(b)
+}
+
+pub fn part1() -> Result<u64, Box<dyn Error>> {
+    run_program(0)
+}
+
+pub fn part2() -> Result<u64, Box<dyn Error>> {
+    run_program(1)
+}
+
+pub fn main() {
+    print_parts(23, part1(), part2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "inc a\njio a, +2\ntpl a\ninc a\n";
+
+    #[test]
+    fn test_parse_line() {
+        assert_eq!(parse_line("hlf a"), Ok(Instruction::Hlf(Register::A)));
+        assert_eq!(parse_line("jmp -7"), Ok(Instruction::Jmp(-7)));
+        assert_eq!(
+            parse_line("jie b, +4"),
+            Ok(Instruction::Jie(Register::B, 4))
+        );
+        assert!(parse_line("jio c, +4").is_err());
+        assert!(parse_line("nop").is_err());
+    }
+
+    #[test]
+    fn test_parse_program() {
+        assert_eq!(parse_program(EXAMPLE).unwrap().len(), 4);
+        assert_eq!(
+            parse_program("inc a\njmp +x\n"),
+            Err("line 2: invalid offset '+x'".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_run() {
+        let program = parse_program(EXAMPLE).unwrap();
+        assert_eq!(Vm::new(0, 0, 100).run(&program), Ok([2, 0]));
+        assert_eq!(Vm::new(1, 5, 100).run(&program), Ok([7, 5]));
+        let looping = parse_program("jmp +0").unwrap();
+        assert!(Vm::new(0, 0, 100).run(&looping).is_err());
+    }
+
+    #[test]
+    fn test_trace() {
+        let program = parse_program(EXAMPLE).unwrap();
+        let mut vm = Vm::new(0, 0, 100).with_trace();
+        vm.run(&program).unwrap();
+        let trace = vm.trace().unwrap();
+        assert_eq!(trace.iter().map(|s| s.pc).collect::<Vec<_>>(), [0, 1, 3]);
+        assert_eq!(trace[2].regs, [1, 0]);
+    }
+}
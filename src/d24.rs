@@ -0,0 +1,178 @@
+use crate::common::{print_parts, read_lines};
+use itertools::Itertools;
+use std::error::Error;
+use std::io::BufRead;
+
+fn parse_line(line: String) -> Option<u64> {
+    line.trim().parse::<u64>().ok()
+}
+
+/// Quantum entanglement of a group, `None` on overflow.
+fn entanglement(group: &[u64]) -> Option<u128> {
+    group
+        .iter()
+        .try_fold(1u128, |acc, w| acc.checked_mul(*w as u128))
+}
+
+fn total_weight(weights: &[u64]) -> u128 {
+    weights.iter().map(|w| *w as u128).sum()
+}
+
+/// Collects every subset of exactly `size` weights summing to `target`.
+/// `weights` must be sorted in decreasing order so that branches can be cut early.
+/// Sums are done in `u128`, which cannot overflow for any number of `u64` weights that fits
+/// in memory.
+fn groups_of_size(weights: &[u64], size: usize, target: u128) -> Vec<Vec<u64>> {
+    fn recurse(
+        weights: &[u64],
+        size: usize,
+        target: u128,
+        current: &mut Vec<u64>,
+        out: &mut Vec<Vec<u64>>,
+    ) {
+        if size == 0 {
+            if target == 0 {
+                out.push(current.clone());
+            }
+            return;
+        }
+        for (i, &w) in weights.iter().enumerate() {
+            let rest = &weights[i + 1..];
+            // Not enough weights left, or even the heaviest ones cannot reach the target
+            if rest.len() < size - 1 || w as u128 * (size as u128) < target {
+                break;
+            }
+            if w as u128 > target {
+                continue;
+            }
+            current.push(w);
+            recurse(rest, size - 1, target - w as u128, current, out);
+            current.pop();
+        }
+    }
+    let mut out = vec![];
+    recurse(weights, size, target, &mut vec![], &mut out);
+    out
+}
+
+/// Whether `weights` can be split into `n` groups each weighing `target`.
+/// `weights` must be sorted in decreasing order.
+fn can_partition(weights: &[u64], n: usize, target: u128) -> bool {
+    fn recurse(weights: &[u64], bins: &mut [u128], target: u128) -> bool {
+        let Some((&w, rest)) = weights.split_first() else {
+            return true;
+        };
+        for i in 0..bins.len() {
+            // Empty bins are interchangeable, only try the first one
+            if bins[i] + w as u128 > target || (bins[i] == 0 && bins[..i].contains(&0)) {
+                continue;
+            }
+            bins[i] += w as u128;
+            if recurse(rest, bins, target) {
+                return true;
+            }
+            bins[i] -= w as u128;
+        }
+        false
+    }
+    total_weight(weights) == n as u128 * target && recurse(weights, &mut vec![0; n], target)
+}
+
+/// Lowest quantum entanglement of the smallest first group when balancing `weights`
+/// into `n` groups of equal weight.
+fn best_entanglement(weights: &[u64], n: usize) -> Result<u128, String> {
+    let total = total_weight(weights);
+    if n == 0 || !total.is_multiple_of(n as u128) {
+        return Err(format!(
+            "Total weight {total} cannot be split in {n} groups"
+        ));
+    }
+    let target = total / n as u128;
+    let sorted = weights
+        .iter()
+        .copied()
+        .sorted_by(|a, b| b.cmp(a))
+        .collect_vec();
+    for size in 1..=sorted.len() {
+        let mut candidates = groups_of_size(&sorted, size, target)
+            .into_iter()
+            .map(|g| Ok((entanglement(&g).ok_or("Quantum entanglement overflow")?, g)))
+            .collect::<Result<Vec<_>, String>>()?;
+        candidates.sort();
+        for (qe, group) in candidates {
+            let mut rest = sorted.clone();
+            group.iter().for_each(|w| {
+                let pos = rest.iter().position(|r| r == w).unwrap();
+                rest.remove(pos);
+            });
+            if can_partition(&rest, n - 1, target) {
+                return Ok(qe);
+            }
+        }
+    }
+    Err("No valid arrangement".to_owned())
+}
+
+pub fn part1() -> Result<u128, Box<dyn Error>> {
+    let weights = read_lines!("data/d24.txt")?
+        .filter_map(parse_line)
+        .collect_vec();
+    Ok(best_entanglement(&weights, 3)?)
+}
+
+pub fn part2() -> Result<u128, Box<dyn Error>> {
+    let weights = read_lines!("data/d24.txt")?
+        .filter_map(parse_line)
+        .collect_vec();
+    Ok(best_entanglement(&weights, 4)?)
+}
+
+pub fn main() {
+    print_parts(24, part1(), part2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WEIGHTS: [u64; 10] = [1, 2, 3, 4, 5, 7, 8, 9, 10, 11];
+
+    #[test]
+    fn test_entanglement() {
+        assert_eq!(entanglement(&[11, 9]), Some(99));
+        assert_eq!(entanglement(&[]), Some(1));
+        assert_eq!(entanglement(&[u64::MAX, u64::MAX, 2]), None);
+    }
+
+    #[test]
+    fn test_groups_of_size() {
+        assert_eq!(groups_of_size(&[11, 10, 9, 8], 2, 20), vec![vec![11, 9]]);
+        assert_eq!(
+            groups_of_size(&[11, 10, 9, 8], 1, 20),
+            Vec::<Vec<u64>>::new()
+        );
+        assert_eq!(
+            groups_of_size(&[3, 2, 2, 1], 2, 4),
+            vec![vec![3, 1], vec![2, 2]]
+        );
+    }
+
+    #[test]
+    fn test_can_partition() {
+        assert!(can_partition(&[5, 4, 3, 2, 1, 1], 2, 8));
+        assert!(!can_partition(&[7, 5, 4], 2, 8));
+        assert!(!can_partition(&[9, 4, 3], 2, 8));
+        assert!(can_partition(&[u64::MAX, u64::MAX, 1, 1], 2, 1 << 64));
+    }
+
+    #[test]
+    fn test_best_entanglement() {
+        assert_eq!(best_entanglement(&WEIGHTS, 3), Ok(99));
+        assert_eq!(best_entanglement(&WEIGHTS, 4), Ok(44));
+        assert!(best_entanglement(&WEIGHTS, 7).is_err());
+        assert_eq!(
+            best_entanglement(&[u64::MAX, u64::MAX, 1, 1], 2),
+            Ok(u64::MAX as u128)
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use crate::common::{print_parts, read_to_string};
+use std::error::Error;
+
+const FIRST_CODE: u64 = 20151125;
+const MULTIPLIER: u64 = 252533;
+const MODULUS: u64 = 33554393;
+
+fn parse_input(content: &str) -> Option<(u64, u64)> {
+    let rest = content.split_once("row")?.1;
+    let (row, col) = rest.split_once(", column")?;
+    let row = row.trim().parse().ok()?;
+    let col = col.trim().trim_end_matches('.').parse().ok()?;
+    (row >= 1 && col >= 1).then_some((row, col))
+}
+
+/// 1-based position of `(row, col)` in the order the codes are filled in,
+/// walking each diagonal from bottom-left to top-right.
+///
+/// Rows and columns are 1-based. Returns `None` if either is 0, or if the position does not
+/// fit in a `u64`.
+pub fn coords_to_index(row: u64, col: u64) -> Option<u64> {
+    if row == 0 || col == 0 {
+        return None;
+    }
+    let diag = row as u128 + col as u128 - 1;
+    let before = diag.checked_mul(diag - 1)? / 2;
+    (before + col as u128).try_into().ok()
+}
+
+/// Inverse of [`coords_to_index`]. Returns `None` for 0, indices starting at 1.
+pub fn index_to_coords(index: u64) -> Option<(u64, u64)> {
+    if index == 0 {
+        return None;
+    }
+    // Largest diagonal whose first index is not past `index`, in `u128` since the last
+    // diagonals of the `u64` range overflow it
+    let index = index as u128;
+    let mut diag = (2 * index).isqrt();
+    while diag * (diag - 1) / 2 >= index {
+        diag -= 1;
+    }
+    while diag * (diag + 1) / 2 < index {
+        diag += 1;
+    }
+    let col = index - diag * (diag - 1) / 2;
+    Some(((diag + 1 - col) as u64, col as u64))
+}
+
+fn mod_pow(mut base: u64, mut exp: u64, modulus: u64) -> u64 {
+    let mut ret = 1;
+    base %= modulus;
+    while exp > 0 {
+        if exp & 1 == 1 {
+            ret = ret * base % modulus;
+        }
+        base = base * base % modulus;
+        exp >>= 1;
+    }
+    ret
+}
+
+/// Code at `(row, col)`, `None` where [`coords_to_index`] is.
+fn code_at(row: u64, col: u64) -> Option<u64> {
+    let index = coords_to_index(row, col)?;
+    Some(FIRST_CODE * mod_pow(MULTIPLIER, index - 1, MODULUS) % MODULUS)
+}
+
+pub fn part1() -> Result<u64, Box<dyn Error>> {
+    let content = read_to_string("data/d25.txt")?;
+    let (row, col) = parse_input(&content).ok_or("Cannot find row and column in input")?;
+    Ok(code_at(row, col)
+        .ok_or_else(|| format!("row {row}, column {col} is too far out to be indexed"))?)
+}
+
+pub fn part2() -> Result<&'static str, String> {
+    Ok("Merry Christmas!")
+}
+
+pub fn main() {
+    print_parts(25, part1(), part2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_input() {
+        let input = "To continue, please consult the code grid in the manual.  \
+                     Enter the code at row 2981, column 3075.\n";
+        assert_eq!(parse_input(input), Some((2981, 3075)));
+        assert_eq!(parse_input("Enter the code."), None);
+    }
+
+    #[test]
+    fn test_coords_to_index() {
+        assert_eq!(coords_to_index(1, 1), Some(1));
+        assert_eq!(coords_to_index(2, 1), Some(2));
+        assert_eq!(coords_to_index(1, 2), Some(3));
+        assert_eq!(coords_to_index(4, 2), Some(12));
+        assert_eq!(coords_to_index(1, 6), Some(21));
+        assert_eq!(coords_to_index(0, 1), None);
+        assert_eq!(coords_to_index(1, 0), None);
+        assert_eq!(coords_to_index(5_000_000_000, 5_000_000_000), None);
+        assert_eq!(coords_to_index(u64::MAX, u64::MAX), None);
+    }
+
+    #[test]
+    fn test_index_to_coords() {
+        assert_eq!(index_to_coords(0), None);
+        assert_eq!(index_to_coords(1), Some((1, 1)));
+        assert_eq!(index_to_coords(14), Some((2, 4)));
+        assert_eq!(index_to_coords(21), Some((1, 6)));
+        for index in (1..5000).chain(u64::MAX - 5000..=u64::MAX) {
+            let (row, col) = index_to_coords(index).unwrap();
+            assert_eq!(coords_to_index(row, col), Some(index));
+        }
+    }
+
+    #[test]
+    fn test_code_at() {
+        assert_eq!(code_at(1, 1), Some(20151125));
+        assert_eq!(code_at(2, 1), Some(31916031));
+        assert_eq!(code_at(1, 2), Some(18749137));
+        assert_eq!(code_at(6, 6), Some(27995004));
+        assert_eq!(code_at(4, 3), Some(21345942));
+        assert_eq!(code_at(5_000_000_000, 5_000_000_000), None);
+    }
+}
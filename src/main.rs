@@ -16,8 +16,28 @@ pub mod d14;
 pub mod d15;
 pub mod d16;
 pub mod d17;
+pub mod d21;
+pub mod d22;
+pub mod d23;
+pub mod d24;
+pub mod d25;
+
+use std::{env, process};
 
 fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Some((day, rest)) = args.split_first() {
+        let ret = match day.as_str() {
+            "d05" => d05::cli(rest),
+            "d07" => d07::cli(rest),
+            _ => Err(format!("unknown day '{day}'").into()),
+        };
+        if let Err(e) = ret {
+            eprintln!("error: {e}");
+            process::exit(1);
+        }
+        return;
+    }
     println!("Hello Advent Of Code 2015 !");
     // d01::main();
     // d02::main();
@@ -35,5 +55,10 @@ fn main() {
     // d14::main();
     // d15::main();
     // d16::main();
-    d17::main();
+    // d17::main();
+    // d21::main();
+    // d22::main();
+    // d23::main();
+    // d24::main();
+    d25::main();
 }